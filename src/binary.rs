@@ -0,0 +1,324 @@
+use crate::cell::{Cell, WidthMode};
+use crate::color::Color;
+use crate::line::Line;
+use crate::pen::{Intensity, Pen};
+use rgb::RGB8;
+
+const FOREGROUND_CHANGED: u8 = 1;
+const BACKGROUND_CHANGED: u8 = 1 << 1;
+const INTENSITY_CHANGED: u8 = 1 << 2;
+const ATTRS_CHANGED: u8 = 1 << 3;
+
+const MAGIC: &[u8] = b"AVT\x01";
+
+// terminal sizes are 16-bit in practice (see TIOCGWINSZ)
+const MAX_SIZE: usize = u16::MAX as usize;
+
+// runs of identical cells take only a few bytes, so the input length alone
+// doesn't bound the allocation size - cap the cell count instead
+const MAX_CELLS: usize = 1 << 21;
+
+pub(crate) struct Encoder {
+    buf: Vec<u8>,
+    pen: Pen,
+}
+
+pub(crate) struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    pen: Pen,
+    width_mode: WidthMode,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Encoder {
+            buf: MAGIC.to_vec(),
+            pen: Pen::default(),
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+
+        self.buf.push(value as u8);
+    }
+
+    pub fn str(&mut self, s: &str) {
+        self.varint(s.len() as u64);
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    pub fn width_mode(&mut self, mode: WidthMode) {
+        self.buf.push(match mode {
+            WidthMode::Standard => 0,
+            WidthMode::AmbiguousWide => 1,
+            WidthMode::EmojiWide => 2,
+        });
+    }
+
+    pub fn lines(&mut self, lines: &[Line]) {
+        for line in lines {
+            self.line(line);
+        }
+    }
+
+    // each line is a wrap flag followed by runs of identical cells, with
+    // pens encoded as a delta from the previous run's pen
+    fn line(&mut self, line: &Line) {
+        let mut runs: Vec<(usize, Cell)> = Vec::new();
+
+        for cell in line.cells() {
+            match runs.last_mut() {
+                Some((n, last)) if last == cell => *n += 1,
+                _ => runs.push((1, cell.clone())),
+            }
+        }
+
+        self.buf.push(line.wrapped() as u8);
+        self.varint(runs.len() as u64);
+
+        for (n, cell) in runs {
+            self.varint(n as u64);
+            self.pen(cell.pen());
+            self.varint(cell.char() as u64);
+            self.str(cell.combining());
+        }
+    }
+
+    fn pen(&mut self, pen: &Pen) {
+        let mut flags = 0;
+
+        if pen.foreground != self.pen.foreground {
+            flags |= FOREGROUND_CHANGED;
+        }
+
+        if pen.background != self.pen.background {
+            flags |= BACKGROUND_CHANGED;
+        }
+
+        if pen.intensity != self.pen.intensity {
+            flags |= INTENSITY_CHANGED;
+        }
+
+        if pen.attrs != self.pen.attrs {
+            flags |= ATTRS_CHANGED;
+        }
+
+        self.buf.push(flags);
+
+        if flags & FOREGROUND_CHANGED != 0 {
+            self.color(pen.foreground);
+        }
+
+        if flags & BACKGROUND_CHANGED != 0 {
+            self.color(pen.background);
+        }
+
+        if flags & INTENSITY_CHANGED != 0 {
+            self.buf.push(match pen.intensity {
+                Intensity::Normal => 0,
+                Intensity::Bold => 1,
+                Intensity::Faint => 2,
+            });
+        }
+
+        if flags & ATTRS_CHANGED != 0 {
+            self.buf.push(pen.attrs);
+        }
+
+        self.pen = *pen;
+    }
+
+    fn color(&mut self, color: Option<Color>) {
+        match color {
+            None => self.buf.push(0),
+
+            Some(Color::Indexed(c)) => {
+                self.buf.push(1);
+                self.buf.push(c);
+            }
+
+            Some(Color::RGB(c)) => {
+                self.buf.push(2);
+                self.buf.extend_from_slice(&[c.r, c.g, c.b]);
+            }
+        }
+    }
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Option<Self> {
+        let bytes = bytes.strip_prefix(MAGIC)?;
+
+        Some(Decoder {
+            bytes,
+            pos: 0,
+            pen: Pen::default(),
+            width_mode: WidthMode::Standard,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+
+    pub fn byte(&mut self) -> Option<u8> {
+        let byte = *self.bytes.get(self.pos)?;
+        self.pos += 1;
+
+        Some(byte)
+    }
+
+    pub fn varint(&mut self) -> Option<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.byte()?;
+
+            if shift > 63 {
+                return None;
+            }
+
+            value |= ((byte & 0x7f) as u64) << shift;
+
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+
+            shift += 7;
+        }
+    }
+
+    pub fn usize(&mut self) -> Option<usize> {
+        self.varint()?.try_into().ok()
+    }
+
+    pub fn str(&mut self) -> Option<&'a str> {
+        let len = self.usize()?;
+        let end = self.pos.checked_add(len)?;
+        let s = std::str::from_utf8(self.bytes.get(self.pos..end)?).ok()?;
+        self.pos = end;
+
+        Some(s)
+    }
+
+    // every line takes at least 2 bytes, which bounds the row count by the
+    // input length
+    pub fn size(&mut self) -> Option<(usize, usize)> {
+        let cols = self.usize()?;
+        let rows = self.usize()?;
+
+        if cols == 0 || cols > MAX_SIZE || rows == 0 || rows > MAX_SIZE {
+            return None;
+        }
+
+        if rows > (self.bytes.len() - self.pos) / 2 || cols * rows > MAX_CELLS {
+            return None;
+        }
+
+        Some((cols, rows))
+    }
+
+    // cell widths of the decoded lines are computed with this mode
+    pub fn width_mode(&mut self) -> Option<WidthMode> {
+        self.width_mode = match self.byte()? {
+            0 => WidthMode::Standard,
+            1 => WidthMode::AmbiguousWide,
+            2 => WidthMode::EmojiWide,
+            _ => return None,
+        };
+
+        Some(self.width_mode)
+    }
+
+    pub fn lines(&mut self, cols: usize, rows: usize) -> Option<Vec<Line>> {
+        (0..rows).map(|_| self.line(cols)).collect()
+    }
+
+    fn line(&mut self, cols: usize) -> Option<Line> {
+        let wrapped = match self.byte()? {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+
+        let run_count = self.usize()?;
+        let mut cells = Vec::new();
+
+        for _ in 0..run_count {
+            let n = self.usize()?;
+            let pen = self.pen()?;
+            let ch = char::from_u32(self.varint()?.try_into().ok()?)?;
+            let combining = self.str()?;
+
+            if n > cols - cells.len() {
+                return None;
+            }
+
+            let mut cell = Cell::with_width_mode(ch, pen, self.width_mode);
+
+            for ch in combining.chars() {
+                cell.combine(ch, self.width_mode);
+            }
+
+            cells.extend(std::iter::repeat(cell).take(n));
+        }
+
+        if cells.len() != cols {
+            return None;
+        }
+
+        Some(Line::new(cells, wrapped))
+    }
+
+    fn pen(&mut self) -> Option<Pen> {
+        let flags = self.byte()?;
+
+        if flags & FOREGROUND_CHANGED != 0 {
+            self.pen.foreground = self.color()?;
+        }
+
+        if flags & BACKGROUND_CHANGED != 0 {
+            self.pen.background = self.color()?;
+        }
+
+        if flags & INTENSITY_CHANGED != 0 {
+            self.pen.intensity = match self.byte()? {
+                0 => Intensity::Normal,
+                1 => Intensity::Bold,
+                2 => Intensity::Faint,
+                _ => return None,
+            };
+        }
+
+        if flags & ATTRS_CHANGED != 0 {
+            self.pen.attrs = self.byte()?;
+        }
+
+        Some(self.pen)
+    }
+
+    fn color(&mut self) -> Option<Option<Color>> {
+        match self.byte()? {
+            0 => Some(None),
+            1 => Some(Some(Color::Indexed(self.byte()?))),
+
+            2 => {
+                let (r, g, b) = (self.byte()?, self.byte()?, self.byte()?);
+
+                Some(Some(Color::RGB(RGB8::new(r, g, b))))
+            }
+
+            _ => None,
+        }
+    }
+}
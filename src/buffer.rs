@@ -1,4 +1,4 @@
-use crate::cell::Cell;
+use crate::cell::{Cell, WidthMode};
 use crate::line::Line;
 use crate::pen::Pen;
 use std::cmp::Ordering;
@@ -11,6 +11,7 @@ pub(crate) struct Buffer {
     pub rows: usize,
     scrollback_limit: Option<ScrollbackLimit>,
     trim_needed: bool,
+    scrolled_lines: usize,
 }
 
 #[derive(Debug)]
@@ -48,14 +49,9 @@ impl Buffer {
             if limit > 0 {
                 lines.reserve(limit);
             }
-        } else {
-            lines.reserve(1000);
         }
 
-        let scrollback_limit = scrollback_limit.map(|l| ScrollbackLimit {
-            soft: l,
-            hard: l + l / 10, // 10% bigger than soft
-        });
+        let scrollback_limit = scrollback_limit.map(ScrollbackLimit::new);
 
         Buffer {
             lines,
@@ -63,35 +59,88 @@ impl Buffer {
             rows,
             scrollback_limit,
             trim_needed: false,
+            scrolled_lines: 0,
+        }
+    }
+
+    pub fn reserve_scrollback(&mut self, n: usize) {
+        self.lines.reserve(n);
+    }
+
+    pub fn set_scrollback_limit(
+        &mut self,
+        limit: Option<usize>,
+        slack: bool,
+    ) -> impl Iterator<Item = Line> + '_ {
+        self.scrollback_limit = limit.map(ScrollbackLimit::new);
+
+        if !slack {
+            self.disable_scrollback_slack();
+        }
+
+        let excess = limit.map_or(0, |limit| self.scrollback_len().saturating_sub(limit));
+
+        self.lines.drain(..excess)
+    }
+
+    pub fn disable_scrollback_slack(&mut self) {
+        if let Some(limit) = &mut self.scrollback_limit {
+            limit.hard = limit.soft;
         }
     }
 
     pub fn text(&self) -> Vec<String> {
+        self.text_with_wraps()
+            .into_iter()
+            .map(|(text, _)| text)
+            .collect()
+    }
+
+    pub fn text_with_wraps(&self) -> Vec<(String, bool)> {
         let mut text = Vec::new();
         let mut current = String::new();
+        let last = self.lines.len() - 1;
 
-        for line in &self.lines {
+        for (i, line) in self.lines.iter().enumerate() {
             current.push_str(&line.text());
 
-            if !line.wrapped {
-                text.push(current.trim_end().to_owned());
+            if !line.wrapped() {
+                text.push((current.trim_end().to_owned(), i < last));
                 current.clear();
             }
         }
 
         if !current.is_empty() {
-            text.push(current.trim_end().to_owned());
+            text.push((current.trim_end().to_owned(), false));
         }
 
         text
     }
 
+    pub fn clear_scrollback(&mut self) -> impl Iterator<Item = Line> + '_ {
+        let scrollback_size = self.scrollback_len();
+
+        self.lines.drain(..scrollback_size)
+    }
+
+    pub fn take_scrolled_lines(&mut self) -> usize {
+        std::mem::take(&mut self.scrolled_lines)
+    }
+
+    pub fn scrollback_len(&self) -> usize {
+        self.lines.len() - self.rows
+    }
+
     pub fn print(&mut self, (col, row): VisualPosition, cell: Cell) {
         self[row].print(col, cell);
     }
 
+    pub fn combine(&mut self, (col, row): VisualPosition, ch: char, mode: WidthMode) {
+        self[row].combine(col, ch, mode);
+    }
+
     pub fn wrap(&mut self, row: usize) {
-        self[row].wrapped = true;
+        self[row].set_wrapped(true);
     }
 
     pub fn insert(&mut self, (col, row): VisualPosition, mut n: usize, cell: Cell) {
@@ -103,7 +152,7 @@ impl Buffer {
         n = n.min(self.cols - col);
         let line = &mut self[row];
         line.delete(col, n, pen);
-        line.wrapped = false;
+        line.set_wrapped(false);
     }
 
     pub fn erase(&mut self, (col, row): VisualPosition, mode: EraseMode, pen: &Pen) {
@@ -118,14 +167,14 @@ impl Buffer {
                 line.clear(col..end, pen);
 
                 if clear_wrap {
-                    line.wrapped = false;
+                    line.set_wrapped(false);
                 }
             }
 
             FromCursorToEndOfView => {
                 let range = col..self.cols;
                 let line = &mut self[row];
-                line.wrapped = false;
+                line.set_wrapped(false);
                 line.clear(range, pen);
                 self.clear((row + 1)..self.rows, pen);
             }
@@ -144,7 +193,7 @@ impl Buffer {
                 let range = col..self.cols;
                 let line = &mut self[row];
                 line.clear(range, pen);
-                line.wrapped = false;
+                line.set_wrapped(false);
             }
 
             FromStartOfLineToCursor => {
@@ -156,7 +205,7 @@ impl Buffer {
                 let range = 0..self.cols;
                 let line = &mut self[row];
                 line.clear(range, pen);
-                line.wrapped = false;
+                line.set_wrapped(false);
             }
         }
     }
@@ -165,22 +214,27 @@ impl Buffer {
         n = n.min(range.end - range.start);
 
         if range.end - 1 < self.rows - 1 {
-            self[range.end - 1].wrapped = false;
+            self[range.end - 1].set_wrapped(false);
         }
 
         if range.start == 0 {
+            // lines leaving the top of the view enter scrollback
+            self.scrolled_lines += n;
+
             if range.end == self.rows {
-                self.extend(n, self.cols);
+                self.extend(n, self.cols, pen);
             } else {
+                debug_assert!(self.lines.len() >= self.rows && range.end <= self.rows);
+
                 let line = Line::blank(self.cols, *pen);
-                let index = self.lines.len() - self.rows + range.end;
+                let index = self.lines.len().saturating_sub(self.rows) + range.end.min(self.rows);
 
                 for _ in 0..n {
                     self.lines.insert(index, line.clone());
                 }
             }
         } else {
-            self[range.start - 1].wrapped = false;
+            self[range.start - 1].set_wrapped(false);
             let end = range.end;
             self[range].rotate_left(n);
             self.clear((end - n)..end, pen);
@@ -196,10 +250,10 @@ impl Buffer {
         self.clear(start..start + n, pen);
 
         if start > 0 {
-            self[start - 1].wrapped = false;
+            self[start - 1].set_wrapped(false);
         }
 
-        self[end - 1].wrapped = false;
+        self[end - 1].set_wrapped(false);
     }
 
     pub fn resize(
@@ -217,7 +271,7 @@ impl Buffer {
             let line_count = self.lines.len();
 
             if line_count < old_rows {
-                self.extend(old_rows - line_count, new_cols);
+                self.extend(old_rows - line_count, new_cols, &Pen::default());
             }
 
             let cursor_rel_pos = self.relative_position(cursor_log_pos, new_cols, old_rows);
@@ -241,7 +295,7 @@ impl Buffer {
 
                 if excess > 0 {
                     self.lines.truncate(line_count - excess);
-                    self.lines.last_mut().unwrap().wrapped = false;
+                    self.lines.last_mut().unwrap().set_wrapped(false);
                 }
 
                 cursor.1 -= height_delta - excess;
@@ -258,7 +312,7 @@ impl Buffer {
                 }
 
                 if height_delta > 0 {
-                    self.extend(height_delta, new_cols);
+                    self.extend(height_delta, new_cols, &Pen::default());
                 }
             }
 
@@ -280,7 +334,7 @@ impl Buffer {
         let mut log_row = abs_row - last_available_row;
 
         for line in self.lines.iter().take(abs_row) {
-            if line.wrapped {
+            if line.wrapped() {
                 log_col_offset += cols;
             } else {
                 log_col_offset = 0;
@@ -303,14 +357,14 @@ impl Buffer {
         let last_row = self.lines.len() - 1;
 
         while r < pos.1 && rel_row < last_row {
-            if !self.lines[rel_row].wrapped {
+            if !self.lines[rel_row].wrapped() {
                 r += 1;
             }
 
             rel_row += 1;
         }
 
-        while rel_col >= cols && self.lines[rel_row].wrapped {
+        while rel_col >= cols && rel_row < last_row && self.lines[rel_row].wrapped() {
             rel_col -= cols;
             rel_row += 1;
         }
@@ -348,8 +402,8 @@ impl Buffer {
         self.view_mut()[range].fill(line);
     }
 
-    fn extend(&mut self, n: usize, cols: usize) {
-        let line = Line::blank(cols, Pen::default());
+    fn extend(&mut self, n: usize, cols: usize, pen: &Pen) {
+        let line = Line::blank(cols, *pen);
         let filler = std::iter::repeat(line).take(n);
         self.lines.extend(filler);
     }
@@ -377,7 +431,7 @@ impl Buffer {
             .map(|(i, line)| {
                 let mut dump = line.dump();
 
-                if i < last && !line.wrapped {
+                if i < last && !line.wrapped() {
                     dump.push('\r');
                     dump.push('\n');
                 }
@@ -401,6 +455,15 @@ impl Buffer {
     }
 }
 
+impl ScrollbackLimit {
+    fn new(limit: usize) -> Self {
+        ScrollbackLimit {
+            soft: limit,
+            hard: limit + limit / 10, // 10% bigger than soft
+        }
+    }
+}
+
 impl Index<usize> for Buffer {
     type Output = Line;
 
@@ -494,7 +557,7 @@ impl<I: Iterator<Item = Line>> Iterator for Reflow<I> {
 
                     None => {
                         line.expand(self.cols, &Pen::default());
-                        line.wrapped = false;
+                        line.set_wrapped(false);
                         return Some(line);
                     }
                 },
@@ -503,7 +566,7 @@ impl<I: Iterator<Item = Line>> Iterator for Reflow<I> {
 
         self.rest.take().map(|mut line| {
             line.expand(self.cols, &Pen::default());
-            line.wrapped = false;
+            line.set_wrapped(false);
 
             line
         })
@@ -521,15 +584,13 @@ mod tests {
     #[test]
     fn text() {
         let mut buffer = Buffer::new(10, 5, None, None);
-        let cell = 'x'.into();
-
         assert_eq!(buffer.text(), vec!["", "", "", "", ""]);
 
-        buffer.print((0, 0), cell);
-        buffer.print((1, 1), cell);
-        buffer.print((2, 2), cell);
-        buffer.print((3, 3), cell);
-        buffer.print((4, 4), cell);
+        buffer.print((0, 0), 'x'.into());
+        buffer.print((1, 1), 'x'.into());
+        buffer.print((2, 2), 'x'.into());
+        buffer.print((3, 3), 'x'.into());
+        buffer.print((4, 4), 'x'.into());
         assert_eq!(buffer.text(), vec!["x", " x", "  x", "   x", "    x"]);
 
         buffer.wrap(0);
@@ -538,6 +599,15 @@ mod tests {
             buffer.text(),
             vec!["x          x", "  x", "   x          x"]
         );
+
+        assert_eq!(
+            buffer.text_with_wraps(),
+            vec![
+                ("x          x".to_owned(), true),
+                ("  x".to_owned(), true),
+                ("   x          x".to_owned(), false)
+            ]
+        );
     }
 
     #[test]
@@ -569,7 +639,7 @@ mod tests {
         assert_eq!(line(&buf[6]), "    ");
         assert_eq!(buf.text().join("\n"), "aaaaaaaaaa\nbb\ncccccccccc\n");
         assert_eq!(buf.lines.len(), 8);
-        assert!(buf.lines[0].wrapped);
+        assert!(buf.lines[0].wrapped());
 
         // top of the view
 
@@ -586,7 +656,7 @@ mod tests {
         assert_eq!(line(&buf[6]), "cc  ");
         assert_eq!(buf.text().join("\n"), "aaaaaaaaaa\nbb\ncccc\n\ncccccc");
         assert_eq!(buf.lines.len(), 8);
-        assert!(buf.lines[0].wrapped);
+        assert!(buf.lines[0].wrapped());
 
         // bottom of the view
 
@@ -640,7 +710,7 @@ mod tests {
     fn line(line: &Line) -> String {
         let mut t = line.text();
 
-        if line.wrapped {
+        if line.wrapped() {
             t.push('⏎');
         }
 
@@ -889,6 +959,19 @@ mod tests {
         assert_eq!(view, vec!["bb", "b ", "cc", "cc", "cc"]);
     }
 
+    #[test]
+    fn relative_position_last_line_wrapped() {
+        let buffer = buffer(&[("aaaa", false), ("bbbb", true)], None, 0);
+
+        assert_eq!(buffer.relative_position((9, 1), 4, 2), (3, 1));
+        assert_eq!(buffer.relative_position((9, 1), 2, 2), (1, 1));
+
+        let (view, cursor) = resize_buffer(0, vec![("aaaa", false), ("bbbb", true)], 1, 2, (3, 1));
+
+        assert_eq!(cursor, (0, 1));
+        assert_eq!(view, vec!["b", "b"]);
+    }
+
     proptest! {
         #[test]
         fn prop_cursor_translation(scrollback_size in 0..20usize, wrapped in prop::collection::vec(prop::bool::ANY, 5), col in 0..10usize, row in 0..5usize) {
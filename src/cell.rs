@@ -1,32 +1,140 @@
-use crate::pen::Pen;
+use crate::pen::{Pen, PROMPT_END, PROMPT_START};
 use unicode_width::UnicodeWidthChar;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub struct Cell(char, Pen);
+const ZWJ: char = '\u{200d}';
+const VS16: char = '\u{fe0f}';
+
+// like xterm, keep only a bounded number of combining chars per cell (enough
+// for emoji ZWJ sequences), dropping the rest
+const MAX_COMBINING: usize = 16;
+
+// base char, pen, combining chars (marks, ZWJ sequences) attached to it, and
+// display width
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cell(char, Pen, Option<String>, u8);
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum WidthMode {
+    // unicode-width as is
+    #[default]
+    Standard,
+    // East Asian ambiguous-width chars are wide
+    AmbiguousWide,
+    // symbols and pictographs, and anything with emoji presentation
+    // selector, are wide
+    EmojiWide,
+}
+
+impl WidthMode {
+    pub(crate) fn cell_width(&self, cell: &Cell) -> u8 {
+        let ch = cell.0;
+
+        let width = match self {
+            WidthMode::Standard => ch.width(),
+            WidthMode::AmbiguousWide => ch.width_cjk(),
+
+            WidthMode::EmojiWide => {
+                if cell.combining().contains(VS16) || is_pictograph(ch) {
+                    Some(2)
+                } else {
+                    ch.width()
+                }
+            }
+        };
+
+        width.unwrap_or(0) as u8
+    }
+}
+
+// combining marks and format controls (ZWSP, ZWJ, soft hyphen, BOM...)
+pub(crate) fn is_zero_width(ch: char) -> bool {
+    ch.width() == Some(0)
+}
+
+fn is_pictograph(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{2600}'..='\u{27bf}' | '\u{2b00}'..='\u{2bff}' | '\u{1f000}'..='\u{1faff}'
+    )
+}
 
 impl Cell {
     pub(crate) fn new(ch: char, pen: Pen) -> Self {
-        Cell(ch, pen)
+        Self::with_width_mode(ch, pen, WidthMode::Standard)
+    }
+
+    pub(crate) fn with_width_mode(ch: char, pen: Pen, mode: WidthMode) -> Self {
+        let mut cell = Cell(ch, pen, None, 0);
+        cell.3 = mode.cell_width(&cell);
+
+        cell
     }
 
     pub(crate) fn blank(pen: Pen) -> Self {
-        Cell(' ', pen)
+        Cell(' ', pen, None, 1)
     }
 
     pub fn is_default(&self) -> bool {
-        self.0 == ' ' && self.1.is_default()
+        self.0 == ' ' && self.1.is_default() && self.2.is_none()
     }
 
     pub fn char(&self) -> char {
         self.0
     }
 
+    pub fn combining(&self) -> &str {
+        self.2.as_deref().unwrap_or("")
+    }
+
+    pub fn text(&self) -> String {
+        let mut s = String::new();
+        self.push_text(&mut s);
+
+        s
+    }
+
+    pub(crate) fn push_text(&self, s: &mut String) {
+        s.push(self.0);
+        s.push_str(self.combining());
+    }
+
+    // whether ch should be attached to this cell instead of printed into a
+    // new one
+    pub(crate) fn combines_with(&self, ch: char) -> bool {
+        // ZWJ sequences join emoji, never ASCII (e.g. the blank cell next to
+        // a trailing ZWJ when a line is replayed from a dump)
+        is_zero_width(ch) || (self.combining().ends_with(ZWJ) && !ch.is_ascii())
+    }
+
+    pub(crate) fn combine(&mut self, ch: char, mode: WidthMode) {
+        let combining = self.2.get_or_insert_with(Default::default);
+
+        if combining.chars().count() >= MAX_COMBINING {
+            return;
+        }
+
+        combining.push(ch);
+        self.3 = mode.cell_width(self);
+    }
+
     pub fn pen(&self) -> &Pen {
         &self.1
     }
 
+    pub fn is_selectable(&self) -> bool {
+        self.1.is_selectable()
+    }
+
+    pub(crate) fn dump(&self) -> String {
+        if self.is_selectable() {
+            format!("{}{}", self.1.dump(), self.text())
+        } else {
+            format!("{}{PROMPT_START}{}{PROMPT_END}", self.1.dump(), self.text())
+        }
+    }
+
     pub fn width(&self) -> usize {
-        self.0.width().unwrap_or(0)
+        self.3 as usize
     }
 }
 
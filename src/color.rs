@@ -1,12 +1,33 @@
 use rgb::RGB8;
 use Color::*;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Color {
     Indexed(u8),
     RGB(RGB8),
 }
 
+pub const DEFAULT_PALETTE: [RGB8; 256] = build_palette();
+
+const BASE_COLORS: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
 impl Color {
     pub(crate) fn sgr_params(&self, base: u8) -> String {
         match self {
@@ -20,4 +41,204 @@ impl Color {
     pub fn rgb(r: u8, g: u8, b: u8) -> Self {
         Self::RGB(RGB8::new(r, g, b))
     }
+
+    pub fn to_rgb(&self, palette: &[RGB8; 256]) -> RGB8 {
+        match self {
+            Indexed(c) => palette[*c as usize],
+            RGB(c) => *c,
+        }
+    }
+
+    pub fn to_indexed_approx(&self) -> u8 {
+        match self {
+            Indexed(c) => *c,
+
+            RGB(c) => {
+                let mut best = 0;
+                let mut best_distance = u32::MAX;
+
+                for (i, p) in DEFAULT_PALETTE.iter().enumerate() {
+                    let distance = distance(*c, *p);
+
+                    if distance < best_distance {
+                        best = i;
+                        best_distance = distance;
+                    }
+                }
+
+                best as u8
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Color {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Indexed(c) => serializer.serialize_u8(*c),
+            RGB(c) => serializer.serialize_str(&format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b)),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ColorVisitor)
+    }
+}
+
+#[cfg(feature = "serde")]
+struct ColorVisitor;
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::Visitor<'de> for ColorVisitor {
+    type Value = Color;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a color index (0-255) or a \"#rrggbb\" string")
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Color, E> {
+        u8::try_from(v)
+            .map(Indexed)
+            .map_err(|_| E::invalid_value(serde::de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Color, E> {
+        u8::try_from(v)
+            .map(Indexed)
+            .map_err(|_| E::invalid_value(serde::de::Unexpected::Signed(v), &self))
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Color, E> {
+        let invalid = || E::invalid_value(serde::de::Unexpected::Str(v), &self);
+        let hex = v.strip_prefix('#').ok_or_else(invalid)?;
+
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+
+        let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+
+        Ok(Color::rgb(component(0)?, component(2)?, component(4)?))
+    }
+}
+
+fn distance(c1: RGB8, c2: RGB8) -> u32 {
+    let dr = c1.r.abs_diff(c2.r) as u32;
+    let dg = c1.g.abs_diff(c2.g) as u32;
+    let db = c1.b.abs_diff(c2.b) as u32;
+
+    dr * dr + dg * dg + db * db
+}
+
+const fn build_palette() -> [RGB8; 256] {
+    let mut palette = [RGB8 { r: 0, g: 0, b: 0 }; 256];
+    let mut i = 0;
+
+    while i < 16 {
+        let (r, g, b) = BASE_COLORS[i];
+        palette[i] = RGB8 { r, g, b };
+        i += 1;
+    }
+
+    while i < 232 {
+        let c = (i - 16) as u8;
+
+        palette[i] = RGB8 {
+            r: cube_level(c / 36),
+            g: cube_level((c / 6) % 6),
+            b: cube_level(c % 6),
+        };
+
+        i += 1;
+    }
+
+    while i < 256 {
+        let level = 8 + (i - 232) as u8 * 10;
+        palette[i] = RGB8 {
+            r: level,
+            g: level,
+            b: level,
+        };
+        i += 1;
+    }
+
+    palette
+}
+
+const fn cube_level(v: u8) -> u8 {
+    if v == 0 {
+        0
+    } else {
+        55 + v * 40
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, DEFAULT_PALETTE};
+    use rgb::RGB8;
+
+    #[test]
+    fn to_rgb() {
+        assert_eq!(
+            Color::Indexed(1).to_rgb(&DEFAULT_PALETTE),
+            RGB8::new(0xcd, 0x00, 0x00)
+        );
+
+        assert_eq!(
+            Color::Indexed(196).to_rgb(&DEFAULT_PALETTE),
+            RGB8::new(0xff, 0x00, 0x00)
+        );
+
+        assert_eq!(
+            Color::Indexed(232).to_rgb(&DEFAULT_PALETTE),
+            RGB8::new(0x08, 0x08, 0x08)
+        );
+
+        assert_eq!(
+            Color::rgb(1, 2, 3).to_rgb(&DEFAULT_PALETTE),
+            RGB8::new(1, 2, 3)
+        );
+    }
+
+    #[test]
+    fn default_palette() {
+        assert_eq!(DEFAULT_PALETTE[15], RGB8::new(0xff, 0xff, 0xff));
+        assert_eq!(DEFAULT_PALETTE[16], RGB8::new(0x00, 0x00, 0x00));
+        assert_eq!(DEFAULT_PALETTE[110], RGB8::new(0x87, 0xaf, 0xd7));
+        assert_eq!(DEFAULT_PALETTE[231], RGB8::new(0xff, 0xff, 0xff));
+        assert_eq!(DEFAULT_PALETTE[255], RGB8::new(0xee, 0xee, 0xee));
+
+        assert_eq!(
+            Color::Indexed(110).to_rgb(&DEFAULT_PALETTE),
+            DEFAULT_PALETTE[110]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let colors = vec![Color::Indexed(5), Color::rgb(0x12, 0xab, 0xff)];
+        let json = serde_json::to_string(&colors).unwrap();
+
+        assert_eq!(json, "[5,\"#12abff\"]");
+        assert_eq!(serde_json::from_str::<Vec<Color>>(&json).unwrap(), colors);
+        assert!(serde_json::from_str::<Color>("256").is_err());
+        assert!(serde_json::from_str::<Color>("\"12abff\"").is_err());
+        assert!(serde_json::from_str::<Color>("\"#12abfg\"").is_err());
+    }
+
+    #[test]
+    fn to_indexed_approx() {
+        assert_eq!(Color::Indexed(42).to_indexed_approx(), 42);
+        assert_eq!(Color::rgb(0, 0, 0).to_indexed_approx(), 0);
+        assert_eq!(Color::rgb(255, 255, 255).to_indexed_approx(), 15);
+        assert_eq!(Color::rgb(0x87, 0x5f, 0xd7).to_indexed_approx(), 98);
+        assert_eq!(Color::rgb(0x80, 0x80, 0x80).to_indexed_approx(), 244);
+        assert_eq!(Color::rgb(0x4f, 0x4e, 0x50).to_indexed_approx(), 239);
+    }
 }
@@ -0,0 +1,250 @@
+use crate::color::{Color, DEFAULT_PALETTE};
+use crate::line::Line;
+use crate::pen::Pen;
+use rgb::RGB8;
+
+#[derive(Debug, Clone)]
+pub struct HtmlOptions {
+    pub foreground: RGB8,
+    pub background: RGB8,
+    pub palette: [RGB8; 256],
+    pub use_classes: bool,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        HtmlOptions {
+            foreground: RGB8::new(0xcc, 0xcc, 0xcc),
+            background: RGB8::new(0x00, 0x00, 0x00),
+            palette: DEFAULT_PALETTE,
+            use_classes: false,
+        }
+    }
+}
+
+impl HtmlOptions {
+    fn rgb(&self, color: Color) -> RGB8 {
+        color.to_rgb(&self.palette)
+    }
+}
+
+pub(crate) fn render(lines: &[Line], opts: &HtmlOptions) -> String {
+    let mut html = String::from("<pre>");
+
+    for line in lines {
+        html.push_str("<div>");
+
+        for cells in line.chunks(|c1, c2| c1.pen() != c2.pen()) {
+            let pen = *cells[0].pen();
+            html.push_str(&open_span(&pen, opts));
+
+            for cell in cells {
+                push_escaped(&mut html, cell.char());
+
+                for ch in cell.combining().chars() {
+                    push_escaped(&mut html, ch);
+                }
+            }
+
+            html.push_str(close_span(&pen, opts));
+        }
+
+        html.push_str("</div>");
+    }
+
+    html.push_str("</pre>");
+
+    html
+}
+
+fn open_span(pen: &Pen, opts: &HtmlOptions) -> String {
+    let mut classes: Vec<String> = Vec::new();
+    let mut styles: Vec<String> = Vec::new();
+
+    let (fg, bg) = if pen.is_inverse() {
+        (
+            pen.background().or(Some(Color::RGB(opts.background))),
+            pen.foreground().or(Some(Color::RGB(opts.foreground))),
+        )
+    } else {
+        (pen.foreground(), pen.background())
+    };
+
+    match fg {
+        Some(Color::Indexed(c)) if opts.use_classes => classes.push(format!("fg-{c}")),
+        Some(c) => styles.push(format!("color: {}", hex(opts.rgb(c)))),
+        None => (),
+    }
+
+    match bg {
+        Some(Color::Indexed(c)) if opts.use_classes => classes.push(format!("bg-{c}")),
+        Some(c) => styles.push(format!("background-color: {}", hex(opts.rgb(c)))),
+        None => (),
+    }
+
+    if opts.use_classes {
+        let flags = [
+            (pen.is_bold(), "bold"),
+            (pen.is_faint(), "faint"),
+            (pen.is_italic(), "italic"),
+            (pen.is_underline(), "underline"),
+            (pen.is_double_underline(), "double-underline"),
+            (pen.is_strikethrough(), "strikethrough"),
+            (pen.is_blink(), "blink"),
+            (!pen.is_selectable(), "unselectable"),
+        ];
+
+        for (set, class) in flags {
+            if set {
+                classes.push(class.to_owned());
+            }
+        }
+    } else {
+        if pen.is_bold() {
+            styles.push("font-weight: bold".to_owned());
+        }
+
+        if pen.is_faint() {
+            styles.push("opacity: 0.5".to_owned());
+        }
+
+        if pen.is_italic() {
+            styles.push("font-style: italic".to_owned());
+        }
+
+        match (pen.is_underline(), pen.is_strikethrough()) {
+            (true, true) => styles.push("text-decoration: underline line-through".to_owned()),
+            (true, false) => styles.push("text-decoration: underline".to_owned()),
+            (false, true) => styles.push("text-decoration: line-through".to_owned()),
+            (false, false) => (),
+        }
+
+        if !pen.is_selectable() {
+            styles.push("user-select: none".to_owned());
+        }
+    }
+
+    let mut span = String::from("<span");
+
+    if !classes.is_empty() {
+        span.push_str(&format!(" class=\"{}\"", classes.join(" ")));
+    }
+
+    if !styles.is_empty() {
+        span.push_str(&format!(" style=\"{}\"", styles.join("; ")));
+    }
+
+    span.push('>');
+
+    // text-decoration-style would make line-through double too, so double
+    // underline goes on a nested span with its own decoration
+    if double_underline_span(pen, opts) {
+        span.push_str("<span style=\"text-decoration: underline double\">");
+    }
+
+    span
+}
+
+fn close_span(pen: &Pen, opts: &HtmlOptions) -> &'static str {
+    if double_underline_span(pen, opts) {
+        "</span></span>"
+    } else {
+        "</span>"
+    }
+}
+
+fn double_underline_span(pen: &Pen, opts: &HtmlOptions) -> bool {
+    !opts.use_classes && pen.is_double_underline()
+}
+
+fn push_escaped(html: &mut String, ch: char) {
+    match ch {
+        '&' => html.push_str("&amp;"),
+        '<' => html.push_str("&lt;"),
+        '>' => html.push_str("&gt;"),
+        '"' => html.push_str("&quot;"),
+        _ => html.push(ch),
+    }
+}
+
+fn hex(c: RGB8) -> String {
+    format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HtmlOptions;
+    use crate::Vt;
+    use rgb::RGB8;
+
+    #[test]
+    fn render() {
+        let mut vt = Vt::new(4, 2);
+
+        vt.feed_str("a<\x1b[1;31mb\x1b[0m\r\n\x1b[38;2;1;2;3;48;5;196m&\x1b[0m");
+
+        assert_eq!(
+            vt.to_html(HtmlOptions::default()),
+            "<pre>\
+             <div><span>a&lt;</span><span style=\"color: #cd0000; font-weight: bold\">b</span><span> </span></div>\
+             <div><span style=\"color: #010203; background-color: #ff0000\">&amp;</span><span>   </span></div>\
+             </pre>"
+        );
+    }
+
+    #[test]
+    fn render_double_underline() {
+        let mut vt = Vt::new(2, 1);
+
+        vt.feed_str("\x1b[4:2;9ma\x1b[29mb");
+
+        assert_eq!(
+            vt.to_html(HtmlOptions::default()),
+            "<pre>\
+             <div><span style=\"text-decoration: line-through\">\
+             <span style=\"text-decoration: underline double\">a</span></span>\
+             <span><span style=\"text-decoration: underline double\">b</span></span></div>\
+             </pre>"
+        );
+    }
+
+    #[test]
+    fn render_with_palette() {
+        let mut vt = Vt::new(2, 1);
+
+        vt.feed_str("\x1b[31ma\x1b[38;5;196mb");
+
+        let mut opts = HtmlOptions::default();
+        opts.palette[1] = RGB8::new(1, 0, 0);
+        opts.palette[196] = RGB8::new(2, 0, 0);
+
+        assert_eq!(
+            vt.to_html(opts),
+            "<pre>\
+             <div><span style=\"color: #010000\">a</span><span style=\"color: #020000\">b</span></div>\
+             </pre>"
+        );
+    }
+
+    #[test]
+    fn render_with_classes() {
+        let mut vt = Vt::new(3, 1);
+
+        vt.feed_str("\x1b[3;4;32;45ma\x1b[0;7mb");
+
+        let opts = HtmlOptions {
+            background: RGB8::new(1, 1, 1),
+            use_classes: true,
+            ..HtmlOptions::default()
+        };
+
+        assert_eq!(
+            vt.to_html(opts),
+            "<pre>\
+             <div><span class=\"fg-2 bg-5 italic underline\">a</span>\
+             <span style=\"color: #010101; background-color: #cccccc\">b</span>\
+             <span> </span></div>\
+             </pre>"
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use crate::terminal::InputModes;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Right,
+    Left,
+    Home,
+    End,
+    Insert,
+    Delete,
+    PageUp,
+    PageDown,
+    F(u8),
+    Keypad(char),
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+}
+
+impl Modifiers {
+    fn is_empty(&self) -> bool {
+        !(self.shift || self.alt || self.ctrl)
+    }
+
+    fn param(&self) -> u8 {
+        1 + self.shift as u8 + (self.alt as u8) * 2 + (self.ctrl as u8) * 4
+    }
+}
+
+pub(crate) fn encode_key(key: Key, mods: Modifiers, modes: &InputModes) -> Option<String> {
+    use Key::*;
+
+    match key {
+        Char(ch) => encode_char(ch, mods),
+        Enter => Some(alt_prefixed("\r", mods)),
+        Tab if mods.shift => Some("\x1b[Z".to_owned()),
+        Tab => Some(alt_prefixed("\t", mods)),
+        Backspace => Some(alt_prefixed("\x7f", mods)),
+        Escape => Some(alt_prefixed("\x1b", mods)),
+        Up => Some(cursor_key('A', mods, modes)),
+        Down => Some(cursor_key('B', mods, modes)),
+        Right => Some(cursor_key('C', mods, modes)),
+        Left => Some(cursor_key('D', mods, modes)),
+        Home => Some(cursor_key('H', mods, modes)),
+        End => Some(cursor_key('F', mods, modes)),
+        Insert => Some(tilde_key(2, mods)),
+        Delete => Some(tilde_key(3, mods)),
+        PageUp => Some(tilde_key(5, mods)),
+        PageDown => Some(tilde_key(6, mods)),
+        F(n @ 1..=4) => Some(ss3_key((b'P' + n - 1) as char, mods)),
+        F(n @ 5..=12) => Some(tilde_key(
+            [15, 17, 18, 19, 20, 21, 23, 24][n as usize - 5],
+            mods,
+        )),
+        F(_) => None,
+        Keypad(ch) => keypad_key(ch, modes),
+    }
+}
+
+fn encode_char(ch: char, mods: Modifiers) -> Option<String> {
+    let ch = if mods.ctrl {
+        match ch {
+            'a'..='z' | 'A'..='Z' => (ch as u8 & 0x1f) as char,
+            '@' | ' ' => '\x00',
+            '[' => '\x1b',
+            '\\' => '\x1c',
+            ']' => '\x1d',
+            '^' => '\x1e',
+            '_' => '\x1f',
+            '?' => '\x7f',
+            _ => return None,
+        }
+    } else {
+        ch
+    };
+
+    Some(alt_prefixed(&ch.to_string(), mods))
+}
+
+fn alt_prefixed(seq: &str, mods: Modifiers) -> String {
+    if mods.alt {
+        format!("\x1b{seq}")
+    } else {
+        seq.to_owned()
+    }
+}
+
+fn cursor_key(finalizer: char, mods: Modifiers, modes: &InputModes) -> String {
+    if modes.vt52_mode {
+        format!("\x1b{finalizer}")
+    } else if !mods.is_empty() {
+        format!("\x1b[1;{}{}", mods.param(), finalizer)
+    } else if modes.cursor_keys_app_mode {
+        format!("\x1bO{finalizer}")
+    } else {
+        format!("\x1b[{finalizer}")
+    }
+}
+
+fn ss3_key(finalizer: char, mods: Modifiers) -> String {
+    if mods.is_empty() {
+        format!("\x1bO{finalizer}")
+    } else {
+        format!("\x1b[1;{}{}", mods.param(), finalizer)
+    }
+}
+
+fn tilde_key(code: u8, mods: Modifiers) -> String {
+    if mods.is_empty() {
+        format!("\x1b[{code}~")
+    } else {
+        format!("\x1b[{};{}~", code, mods.param())
+    }
+}
+
+fn keypad_key(ch: char, modes: &InputModes) -> Option<String> {
+    let finalizer = match ch {
+        '0'..='9' => (b'p' + (ch as u8 - b'0')) as char,
+        '*' => 'j',
+        '+' => 'k',
+        ',' => 'l',
+        '-' => 'm',
+        '.' => 'n',
+        '/' => 'o',
+        '=' => 'X',
+        '\r' => 'M',
+        _ => return None,
+    };
+
+    if modes.keypad_app_mode && modes.vt52_mode {
+        Some(format!("\x1b?{finalizer}"))
+    } else if modes.keypad_app_mode {
+        Some(format!("\x1bO{finalizer}"))
+    } else {
+        Some(ch.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_key, Key, Modifiers};
+    use crate::terminal::{InputModes, MouseEncoding, MouseMode};
+
+    fn modes(cursor_keys_app_mode: bool, keypad_app_mode: bool) -> InputModes {
+        InputModes {
+            cursor_keys_app_mode,
+            keypad_app_mode,
+            bracketed_paste: false,
+            focus_reporting: false,
+            mouse_mode: MouseMode::Off,
+            mouse_encoding: MouseEncoding::Default,
+            vt52_mode: false,
+        }
+    }
+
+    fn encode(key: Key, mods: Modifiers, modes: InputModes) -> Option<String> {
+        encode_key(key, mods, &modes)
+    }
+
+    #[test]
+    fn cursor_keys() {
+        let none = Modifiers::default();
+
+        let ctrl = Modifiers {
+            ctrl: true,
+            ..Modifiers::default()
+        };
+
+        let shift_alt = Modifiers {
+            shift: true,
+            alt: true,
+            ..Modifiers::default()
+        };
+
+        let normal = modes(false, false);
+        let app = modes(true, false);
+
+        assert_eq!(encode(Key::Up, none, normal).unwrap(), "\x1b[A");
+        assert_eq!(encode(Key::Up, none, app).unwrap(), "\x1bOA");
+        assert_eq!(encode(Key::Left, ctrl, app).unwrap(), "\x1b[1;5D");
+        assert_eq!(encode(Key::Home, none, app).unwrap(), "\x1bOH");
+        assert_eq!(encode(Key::End, shift_alt, normal).unwrap(), "\x1b[1;4F");
+    }
+
+    #[test]
+    fn editing_and_function_keys() {
+        let none = Modifiers::default();
+
+        let shift = Modifiers {
+            shift: true,
+            ..Modifiers::default()
+        };
+
+        let normal = modes(false, false);
+
+        assert_eq!(encode(Key::Delete, none, normal).unwrap(), "\x1b[3~");
+        assert_eq!(encode(Key::PageUp, shift, normal).unwrap(), "\x1b[5;2~");
+        assert_eq!(encode(Key::F(1), none, normal).unwrap(), "\x1bOP");
+        assert_eq!(encode(Key::F(4), shift, normal).unwrap(), "\x1b[1;2S");
+        assert_eq!(encode(Key::F(5), none, normal).unwrap(), "\x1b[15~");
+        assert_eq!(encode(Key::F(12), shift, normal).unwrap(), "\x1b[24;2~");
+        assert_eq!(encode(Key::F(13), none, normal), None);
+        assert_eq!(encode(Key::Tab, shift, normal).unwrap(), "\x1b[Z");
+    }
+
+    #[test]
+    fn chars() {
+        let none = Modifiers::default();
+
+        let ctrl = Modifiers {
+            ctrl: true,
+            ..Modifiers::default()
+        };
+
+        let alt = Modifiers {
+            alt: true,
+            ..Modifiers::default()
+        };
+
+        let normal = modes(false, false);
+
+        assert_eq!(encode(Key::Char('a'), none, normal).unwrap(), "a");
+        assert_eq!(encode(Key::Char('c'), ctrl, normal).unwrap(), "\x03");
+        assert_eq!(encode(Key::Char('x'), alt, normal).unwrap(), "\x1bx");
+        assert_eq!(encode(Key::Backspace, alt, normal).unwrap(), "\x1b\x7f");
+        assert_eq!(encode(Key::Char('1'), ctrl, normal), None);
+    }
+
+    #[test]
+    fn keypad() {
+        let none = Modifiers::default();
+        let normal = modes(false, false);
+        let app = modes(false, true);
+
+        assert_eq!(encode(Key::Keypad('5'), none, normal).unwrap(), "5");
+        assert_eq!(encode(Key::Keypad('5'), none, app).unwrap(), "\x1bOu");
+        assert_eq!(encode(Key::Keypad('\r'), none, app).unwrap(), "\x1bOM");
+        assert_eq!(encode(Key::Keypad('-'), none, app).unwrap(), "\x1bOm");
+        assert_eq!(encode(Key::Keypad('a'), none, app), None);
+    }
+
+    #[test]
+    fn vt52() {
+        let none = Modifiers::default();
+
+        let vt52 = InputModes {
+            vt52_mode: true,
+            ..modes(true, false)
+        };
+
+        let vt52_app = InputModes {
+            vt52_mode: true,
+            ..modes(false, true)
+        };
+
+        assert_eq!(encode(Key::Up, none, vt52).unwrap(), "\x1bA");
+        assert_eq!(encode(Key::Left, none, vt52_app).unwrap(), "\x1bD");
+        assert_eq!(encode(Key::Keypad('5'), none, vt52).unwrap(), "5");
+        assert_eq!(encode(Key::Keypad('5'), none, vt52_app).unwrap(), "\x1b?u");
+        assert_eq!(encode(Key::Keypad('\r'), none, vt52_app).unwrap(), "\x1b?M");
+    }
+}
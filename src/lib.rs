@@ -1,7 +1,12 @@
+#[cfg(feature = "binary")]
+mod binary;
 mod buffer;
 mod cell;
 mod charset;
 mod color;
+#[cfg(feature = "html")]
+mod html;
+mod input;
 mod line;
 pub mod parser;
 mod pen;
@@ -9,8 +14,19 @@ mod tabs;
 mod terminal;
 pub mod util;
 mod vt;
-pub use cell::Cell;
-pub use color::Color;
+pub use cell::{Cell, WidthMode};
+pub use charset::Charset;
+pub use color::{Color, DEFAULT_PALETTE};
+#[cfg(feature = "html")]
+pub use html::HtmlOptions;
+pub use input::{Key, Modifiers};
 pub use line::Line;
-pub use pen::Pen;
-pub use vt::Vt;
+pub use parser::{
+    AnsiMode, CtcOp, DecMode, DsrOp, EdScope, ElScope, Function, OscOp, SgrOp, TbcScope, XtwinopsOp,
+};
+pub use pen::{Intensity, Pen};
+pub use terminal::BufferTransition;
+pub use terminal::Cursor;
+pub use terminal::Sixel;
+pub use terminal::{InputModes, MouseEncoding, MouseMode};
+pub use vt::{Vt, VtEvent};
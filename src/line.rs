@@ -1,38 +1,57 @@
-use crate::cell::Cell;
-use crate::pen::Pen;
+use crate::cell::{Cell, WidthMode};
+use crate::pen::{Pen, PROMPT_END, PROMPT_START};
+use std::hash::{Hash, Hasher};
 use std::ops::{Index, Range, RangeFull};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-#[derive(Debug, Clone, PartialEq)]
+static NEXT_REV: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, Eq)]
 pub struct Line {
     pub(crate) cells: Vec<Cell>,
-    pub(crate) wrapped: bool,
+    wrapped: bool,
+    rev: u64,
 }
 
 impl Line {
-    pub(crate) fn blank(cols: usize, pen: Pen) -> Self {
+    pub(crate) fn new(cells: Vec<Cell>, wrapped: bool) -> Self {
         Line {
-            cells: vec![Cell::blank(pen); cols],
-            wrapped: false,
+            cells,
+            wrapped,
+            rev: next_rev(),
         }
     }
 
+    pub(crate) fn blank(cols: usize, pen: Pen) -> Self {
+        Line::new(vec![Cell::blank(pen); cols], false)
+    }
+
     pub(crate) fn clear(&mut self, range: Range<usize>, pen: &Pen) {
         self.cells[range].fill(Cell::blank(*pen));
+        self.touch();
     }
 
     pub(crate) fn print(&mut self, col: usize, cell: Cell) {
         self.cells[col] = cell;
+        self.touch();
+    }
+
+    pub(crate) fn combine(&mut self, col: usize, ch: char, mode: WidthMode) {
+        self.cells[col].combine(ch, mode);
+        self.touch();
     }
 
     pub(crate) fn insert(&mut self, col: usize, n: usize, cell: Cell) {
         self.cells[col..].rotate_right(n);
         self.cells[col..col + n].fill(cell);
+        self.touch();
     }
 
     pub(crate) fn delete(&mut self, col: usize, n: usize, pen: &Pen) {
         self.cells[col..].rotate_left(n);
         let start = self.cells.len() - n;
         self.cells[start..].fill(Cell::blank(*pen));
+        self.touch();
     }
 
     pub(crate) fn extend(&mut self, mut other: Line, len: usize) -> (bool, Option<Line>) {
@@ -42,6 +61,8 @@ impl Line {
             return (true, Some(other));
         }
 
+        self.touch();
+
         if !self.wrapped {
             self.expand(len, &Pen::default());
 
@@ -53,24 +74,18 @@ impl Line {
         }
 
         if needed < other.len() {
-            self.cells.extend(&other[0..needed]);
+            self.cells.extend_from_slice(&other[0..needed]);
             let mut cells = other.cells;
             cells.rotate_left(needed);
             cells.truncate(cells.len() - needed);
 
-            return (
-                true,
-                Some(Line {
-                    cells,
-                    wrapped: other.wrapped,
-                }),
-            );
+            return (true, Some(Line::new(cells, other.wrapped)));
         }
 
-        self.cells.extend(&other[..]);
+        self.cells.extend_from_slice(&other[..]);
 
         if !other.wrapped {
-            self.wrapped = false;
+            self.set_wrapped(false);
 
             if self.len() < len {
                 self.expand(len, &Pen::default());
@@ -86,19 +101,19 @@ impl Line {
         let tpl = Cell::blank(*pen);
         let filler = std::iter::repeat(tpl).take(len - self.len());
         self.cells.extend(filler);
+        self.touch();
     }
 
     pub(crate) fn contract(&mut self, len: usize) -> Option<Line> {
+        self.touch();
+
         if !self.wrapped {
             let trimmed_len = self.len() - self.trailers();
             self.cells.truncate(len.max(trimmed_len));
         }
 
         if self.len() > len {
-            let mut rest = Line {
-                cells: self.cells.split_off(len),
-                wrapped: self.wrapped,
-            };
+            let mut rest = Line::new(self.cells.split_off(len), self.wrapped);
 
             if !self.wrapped {
                 rest.trim();
@@ -107,7 +122,7 @@ impl Line {
             if rest.cells.is_empty() {
                 None
             } else {
-                self.wrapped = true;
+                self.set_wrapped(true);
 
                 Some(rest)
             }
@@ -125,10 +140,54 @@ impl Line {
         self.len() == 0
     }
 
+    pub fn width(&self) -> usize {
+        self.cells.iter().map(Cell::width).sum()
+    }
+
+    pub fn content_width(&self) -> usize {
+        match self.last_non_blank_col() {
+            Some(col) => self.cells[..=col].iter().map(Cell::width).sum(),
+            None => 0,
+        }
+    }
+
+    pub fn wrapped(&self) -> bool {
+        self.wrapped
+    }
+
+    pub(crate) fn set_wrapped(&mut self, wrapped: bool) {
+        if self.wrapped != wrapped {
+            self.wrapped = wrapped;
+            self.touch();
+        }
+    }
+
+    pub fn rev(&self) -> u64 {
+        self.rev
+    }
+
+    fn touch(&mut self) {
+        self.rev = next_rev();
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.cells.iter().all(Cell::is_default)
+    }
+
+    pub fn last_non_blank_col(&self) -> Option<usize> {
+        self.cells.iter().rposition(|cell| !cell.is_default())
+    }
+
     pub fn cells(&self) -> &[Cell] {
         &self.cells
     }
 
+    // zero-width chars are combined into the preceding cell, so every cell
+    // occupies exactly one column
+    pub fn cells_with_cols(&self) -> impl Iterator<Item = (usize, &Cell)> + '_ {
+        self.cells.iter().enumerate()
+    }
+
     pub fn chunks<'a>(
         &'a self,
         predicate: impl Fn(&Cell, &Cell) -> bool + 'a,
@@ -141,7 +200,43 @@ impl Line {
     }
 
     pub fn text(&self) -> String {
-        self.chars().collect()
+        let mut s = String::new();
+
+        for cell in &self.cells {
+            cell.push_text(&mut s);
+        }
+
+        s
+    }
+
+    pub fn to_ansi(&self) -> String {
+        let len = if self.wrapped {
+            self.len()
+        } else {
+            self.len() - self.trailers()
+        };
+
+        let mut s = String::new();
+        let mut prev_pen: Option<Pen> = None;
+
+        for cells in Chunks::new(self.cells[..len].iter(), |c1, c2| c1.pen() != c2.pen()) {
+            let pen = *cells[0].pen();
+
+            match prev_pen {
+                Some(prev_pen) => s.push_str(&pen.sgr_diff(&prev_pen)),
+                None => s.push_str(&pen.dump()),
+            }
+
+            prev_pen = Some(pen);
+
+            for cell in cells {
+                cell.push_text(&mut s);
+            }
+        }
+
+        s.push_str("\x1b[0m");
+
+        s
     }
 
     fn trim(&mut self) {
@@ -162,19 +257,48 @@ impl Line {
 
     pub fn dump(&self) -> String {
         let mut s = String::new();
+        let mut selectable = true;
 
         for cells in self.chunks(|c1, c2| c1.pen() != c2.pen()) {
-            s.push_str(&cells[0].pen().dump());
+            let pen = cells[0].pen();
+
+            if pen.is_selectable() != selectable {
+                selectable = pen.is_selectable();
+                s.push_str(if selectable { PROMPT_END } else { PROMPT_START });
+            }
+
+            s.push_str(&pen.dump());
 
             for cell in cells {
-                s.push(cell.char());
+                cell.push_text(&mut s);
             }
         }
 
+        if !selectable {
+            s.push_str(PROMPT_END);
+        }
+
         s
     }
 }
 
+impl PartialEq for Line {
+    fn eq(&self, other: &Self) -> bool {
+        self.cells == other.cells && self.wrapped == other.wrapped
+    }
+}
+
+impl Hash for Line {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.cells.hash(state);
+        self.wrapped.hash(state);
+    }
+}
+
+fn next_rev() -> u64 {
+    NEXT_REV.fetch_add(1, Ordering::Relaxed)
+}
+
 struct Chunks<'a, I, F>
 where
     I: Iterator<Item = &'a Cell>,
@@ -201,16 +325,16 @@ impl<'a, I: Iterator<Item = &'a Cell>, F: Fn(&Cell, &Cell) -> bool> Iterator for
     fn next(&mut self) -> Option<Self::Item> {
         for cell in self.iter.by_ref() {
             if self.cells.is_empty() {
-                self.cells.push(*cell);
+                self.cells.push(cell.clone());
                 continue;
             }
 
             if (self.predicate)(self.cells.last().unwrap(), cell) {
                 let cells = std::mem::take(&mut self.cells);
-                self.cells.push(*cell);
+                self.cells.push(cell.clone());
                 return Some(cells);
             } else {
-                self.cells.push(*cell);
+                self.cells.push(cell.clone());
             }
         }
 
@@ -248,12 +372,124 @@ impl Index<RangeFull> for Line {
 
 #[cfg(test)]
 mod tests {
-    use super::{Cell, Chunks};
+    use super::{Cell, Chunks, Line};
+    use crate::pen::Pen;
+    use crate::vt::Vt;
 
     fn chars(cells: &[Cell]) -> Vec<char> {
         cells.iter().map(|c| c.char()).collect()
     }
 
+    #[test]
+    fn blank() {
+        let mut line = Line::blank(5, Pen::default());
+
+        assert!(line.is_blank());
+        assert_eq!(line.last_non_blank_col(), None);
+
+        line.print(1, 'a'.into());
+        line.print(2, 'b'.into());
+
+        assert!(!line.is_blank());
+        assert_eq!(line.last_non_blank_col(), Some(2));
+
+        let mut pen = Pen::default();
+        pen.set_inverse();
+        let line = Line::blank(5, pen);
+
+        assert!(!line.is_blank());
+        assert_eq!(line.last_non_blank_col(), Some(4));
+    }
+
+    #[test]
+    fn rev() {
+        let mut line = Line::blank(5, Pen::default());
+        let other = Line::blank(5, Pen::default());
+        let rev = line.rev();
+
+        assert_ne!(other.rev(), rev);
+        assert_eq!(line, other);
+
+        line.print(0, 'a'.into());
+
+        assert!(line.rev() > rev);
+
+        let rev = line.rev();
+        let copy = line.clone();
+
+        assert_eq!(copy.rev(), rev);
+
+        line.delete(0, 1, &Pen::default());
+
+        assert!(line.rev() > rev);
+
+        let rev = line.rev();
+        line.set_wrapped(true);
+
+        assert!(line.rev() > rev);
+
+        let rev = line.rev();
+        line.set_wrapped(true);
+
+        assert_eq!(line.rev(), rev);
+    }
+
+    #[test]
+    fn width() {
+        let mut line = Line::blank(5, Pen::default());
+
+        assert_eq!(line.width(), 5);
+
+        line.print(0, 'a'.into());
+        line.print(1, '\u{301}'.into());
+        line.print(2, '\u{3042}'.into());
+
+        assert_eq!(line.width(), 5);
+
+        line.print(3, '\u{3042}'.into());
+
+        assert_eq!(line.width(), 6);
+    }
+
+    #[test]
+    fn content_width() {
+        let mut line = Line::blank(6, Pen::default());
+
+        assert_eq!(line.content_width(), 0);
+
+        line.print(0, 'a'.into());
+        line.print(1, '\u{3042}'.into());
+
+        assert_eq!(line.content_width(), 3);
+
+        line.print(4, 'b'.into());
+
+        assert_eq!(line.content_width(), 6);
+    }
+
+    #[test]
+    fn cells_with_cols() {
+        let mut vt = Vt::new(5, 1);
+        vt.feed_str("a\u{301}b");
+
+        let cols: Vec<(usize, String)> = vt
+            .line(0)
+            .cells_with_cols()
+            .map(|(col, cell)| (col, format!("{}{}", cell.char(), cell.combining())))
+            .collect();
+
+        assert_eq!(
+            cols,
+            vec![
+                (0, "a\u{301}".to_owned()),
+                (1, "b".to_owned()),
+                (2, " ".to_owned()),
+                (3, " ".to_owned()),
+                (4, " ".to_owned()),
+            ]
+        );
+    }
+
     #[test]
     fn chunks() {
         let cells = [
@@ -4,15 +4,46 @@
 use crate::charset::Charset;
 use crate::color::Color;
 use std::fmt::Display;
+use std::mem;
 
 const PARAMS_LEN: usize = 32;
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Parser {
     pub state: State,
     params: [Param; PARAMS_LEN],
     cur_param: usize,
+    params_overflow: bool,
     intermediate: Option<char>,
+    dcs_hook: Option<DcsHook>,
+    dcs_data: String,
+    osc_data: Option<String>,
+    apc_data: Option<String>,
+    max_string_len: usize,
+    vt52_mode: bool,
+    vt52_state: Vt52State,
+    unknown_sequences: Option<Vec<String>>,
+    raw_seq: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Vt52State {
+    Ground,
+    Escape,
+    Row,
+    Col(char),
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Parser::with_max_string_len(DEFAULT_MAX_STRING_LEN)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum DcsHook {
+    Sixel,
+    Decrqss,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
@@ -36,6 +67,8 @@ pub enum State {
 
 #[derive(Debug, PartialEq)]
 pub enum Function {
+    Apc(String),
+    Bel,
     Bs,
     Cbt(u16),
     Cha(u16),
@@ -51,16 +84,22 @@ pub enum Function {
     Cuu(u16),
     Dch(u16),
     Decaln,
+    Deckpam,
+    Deckpnm,
     Decrc,
+    Decrqss(String),
     Decrst(Vec<DecMode>),
     Decsc,
     Decset(Vec<DecMode>),
     Decstbm(u16, u16),
     Decstr,
     Dl(u16),
+    Dsr(DsrOp),
     Ech(u16),
     Ed(EdScope),
     El(ElScope),
+    Enq,
+    Ff,
     G1d4(Charset),
     Gzd4(Charset),
     Ht,
@@ -69,22 +108,27 @@ pub enum Function {
     Il(u16),
     Lf,
     Nel,
+    Osc(OscOp),
     Print(char),
     Rep(u16),
     Ri,
     Ris,
     Rm(Vec<AnsiMode>),
+    S7c1t,
+    S8c1t,
     Scorc,
     Scosc,
     Sd(u16),
     Sgr(Vec<SgrOp>),
     Si,
+    Sixel(String),
     Sm(Vec<AnsiMode>),
     So,
     Su(u16),
     Tbc(TbcScope),
     Vpa(u16),
     Vpr(u16),
+    Vt52Identify,
     Xtwinops(XtwinopsOp),
 }
 
@@ -106,12 +150,32 @@ pub enum CtcOp {
 #[repr(u16)]
 pub enum DecMode {
     CursorKeys = 1,                   // DECCKM
+    Ansi = 2,                         // DECANM
+    ReverseScreen = 5,                // DECSCNM
     Origin = 6,                       // DECOM
     AutoWrap = 7,                     // DECAWM
+    X10Mouse = 9,                     // xterm
+    CursorBlink = 12,                 // att610
     TextCursorEnable = 25,            // DECTCEM
+    SixelDisplay = 80,                // DECSDM
+    NormalMouse = 1000,               // xterm
+    ButtonEventMouse = 1002,          // xterm
+    AnyEventMouse = 1003,             // xterm
+    FocusEvent = 1004,                // xterm
+    Utf8Mouse = 1005,                 // xterm
+    SgrMouse = 1006,                  // xterm
+    UrxvtMouse = 1015,                // urxvt
     AltScreenBuffer = 1047,           // xterm
     SaveCursor = 1048,                // xterm
     SaveCursorAltScreenBuffer = 1049, // xterm
+    PrivateColorRegisters = 1070,     // xterm
+    BracketedPaste = 2004,            // xterm
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DsrOp {
+    Status,
+    CursorPosition,
 }
 
 #[derive(Debug, PartialEq)]
@@ -139,7 +203,8 @@ pub enum SgrOp {
     SetBlink,                  // 5
     SetInverse,                // 7
     SetStrikethrough,          // 9
-    ResetIntensity,            // 21, 22
+    SetDoubleUnderline,        // 21
+    ResetIntensity,            // 22
     ResetItalic,               // 23
     ResetUnderline,            // 24
     ResetBlink,                // 25
@@ -151,6 +216,13 @@ pub enum SgrOp {
     ResetBackgroundColor,      // 49
 }
 
+#[derive(Debug, PartialEq)]
+pub enum OscOp {
+    SetTitle(String),
+    PromptStart,
+    PromptEnd,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum TbcScope {
     CurrentColumn,
@@ -160,6 +232,11 @@ pub enum TbcScope {
 #[derive(Debug, PartialEq)]
 pub enum XtwinopsOp {
     Resize(u16, u16),
+    ReportWindowState,
+    ReportWindowPosition,
+    ReportPixelSize,
+    ReportTextAreaSize,
+    ReportScreenSize,
 }
 
 impl Parser {
@@ -167,13 +244,98 @@ impl Parser {
         Default::default()
     }
 
+    pub fn with_max_string_len(max_string_len: usize) -> Self {
+        Parser {
+            state: State::default(),
+            params: Default::default(),
+            cur_param: 0,
+            params_overflow: false,
+            intermediate: None,
+            dcs_hook: None,
+            dcs_data: String::new(),
+            osc_data: None,
+            apc_data: None,
+            max_string_len,
+            vt52_mode: false,
+            vt52_state: Vt52State::Ground,
+            unknown_sequences: None,
+            raw_seq: String::new(),
+        }
+    }
+
+    // when enabled, well-formed but unsupported ESC/CSI sequences (including
+    // SM/RM/DECSET/DECRST with unsupported modes) are recorded (as received,
+    // up to max_string_len) for take_unknown_sequences
+    pub fn collect_unknown_sequences(&mut self, enabled: bool) {
+        self.unknown_sequences = enabled.then(Vec::new);
+    }
+
+    pub fn take_unknown_sequences(&mut self) -> Vec<String> {
+        self.unknown_sequences
+            .as_mut()
+            .map(mem::take)
+            .unwrap_or_default()
+    }
+
     pub fn feed(&mut self, input: char) -> Option<Function> {
+        if self.vt52_mode {
+            return self.vt52_advance(input);
+        }
+
+        let state = self.state;
+
+        if self.unknown_sequences.is_some() {
+            self.record_raw(state, input);
+        }
+
+        // DCS and APC are dispatched only when terminated with ST, either
+        // 8-bit or ESC \ - CAN, SUB and other C1 controls cancel them
+        let pending_st = state == State::Escape;
+        let fun = self.advance(input);
+
+        if pending_st && self.dcs_hook.is_some() {
+            if input == '\\' {
+                return self.unhook().or(fun);
+            }
+
+            self.cancel_dcs();
+        }
+
+        if pending_st && self.apc_data.is_some() && self.state != State::SosPmApcString {
+            if input == '\\' {
+                return self.apc_end().or(fun);
+            }
+
+            self.apc_data = None;
+        }
+
+        if self.state != state {
+            match state {
+                State::DcsPassthrough => match input {
+                    '\u{9c}' => return self.unhook().or(fun),
+                    '\u{1b}' => {}
+                    _ => self.cancel_dcs(),
+                },
+                State::OscString => return self.osc_end().or(fun),
+                State::SosPmApcString => match input {
+                    '\u{9c}' => return self.apc_end().or(fun),
+                    '\u{1b}' => {}
+                    _ => self.apc_data = None,
+                },
+                _ => {}
+            }
+        }
+
+        fun
+    }
+
+    fn advance(&mut self, input: char) -> Option<Function> {
         use State::*;
 
         let input2 = if input >= '\u{a0}' { '\u{41}' } else { input };
 
         match (&self.state, input2) {
-            (Ground, '\u{20}'..='\u{7f}') => {
+            (Ground, '\u{20}'..='\u{7e}') => {
                 return Some(Function::Print(input));
             }
 
@@ -214,6 +376,10 @@ impl Parser {
                 self.osc_put(input);
             }
 
+            (SosPmApcString, '\u{20}'..='\u{7f}') => {
+                self.apc_put(input);
+            }
+
             (Escape, '\u{20}'..='\u{2f}') => {
                 self.state = EscapeIntermediate;
                 self.collect(input);
@@ -235,6 +401,7 @@ impl Parser {
 
             (CsiIgnore, '\u{40}'..='\u{7e}') => {
                 self.state = Ground;
+                self.unknown();
             }
 
             (CsiParam, '\u{3c}'..='\u{3f}') => {
@@ -253,6 +420,7 @@ impl Parser {
 
             (Escape, '\u{5d}') => {
                 self.state = OscString;
+                self.osc_start();
             }
 
             (OscString, '\u{07}') => {
@@ -291,6 +459,7 @@ impl Parser {
 
             (DcsParam, '\u{40}'..='\u{7e}') => {
                 self.state = DcsPassthrough;
+                self.hook(input);
             }
 
             (DcsEntry, '\u{3c}'..='\u{3f}') => {
@@ -315,6 +484,7 @@ impl Parser {
 
             (DcsIntermediate, '\u{40}'..='\u{7e}') => {
                 self.state = DcsPassthrough;
+                self.hook(input);
             }
 
             (DcsPassthrough, '\u{00}'..='\u{17}')
@@ -331,6 +501,7 @@ impl Parser {
 
             (DcsEntry, '\u{40}'..='\u{7e}') => {
                 self.state = DcsPassthrough;
+                self.hook(input);
             }
 
             (CsiIntermediate, '\u{20}'..='\u{2f}') => {
@@ -356,11 +527,12 @@ impl Parser {
                 return self.execute(input);
             }
 
-            (Escape, '\u{58}') | (Escape, '\u{5e}') | (Escape, '\u{5f}') => {
+            (Escape, '\u{5f}') | (_, '\u{9f}') => {
                 self.state = SosPmApcString;
+                self.apc_start();
             }
 
-            (_, '\u{98}') | (_, '\u{9e}') | (_, '\u{9f}') => {
+            (Escape, '\u{58}') | (Escape, '\u{5e}') | (_, '\u{98}') | (_, '\u{9e}') => {
                 self.state = SosPmApcString;
             }
 
@@ -370,6 +542,7 @@ impl Parser {
 
             (_, '\u{9d}') => {
                 self.state = OscString;
+                self.osc_start();
             }
 
             (_, '\u{90}') => {
@@ -434,11 +607,13 @@ impl Parser {
         use Function::*;
 
         match input {
+            '\u{05}' => Some(Enq),
+            '\u{07}' => Some(Bel),
             '\u{08}' => Some(Bs),
             '\u{09}' => Some(Ht),
             '\u{0a}' => Some(Lf),
             '\u{0b}' => Some(Lf),
-            '\u{0c}' => Some(Lf),
+            '\u{0c}' => Some(Ff),
             '\u{0d}' => Some(Cr),
             '\u{0e}' => Some(So),
             '\u{0f}' => Some(Si),
@@ -456,6 +631,7 @@ impl Parser {
         }
 
         self.cur_param = 0;
+        self.params_overflow = false;
         self.intermediate = None;
     }
 
@@ -464,11 +640,15 @@ impl Parser {
     }
 
     fn param(&mut self, input: char) {
-        if input == ';' {
-            self.cur_param += 1;
+        if self.params_overflow {
+            return;
+        }
 
-            if self.cur_param == PARAMS_LEN {
-                self.cur_param = PARAMS_LEN - 1;
+        if input == ';' {
+            if self.cur_param == PARAMS_LEN - 1 {
+                self.params_overflow = true;
+            } else {
+                self.cur_param += 1;
             }
         } else if input == ':' {
             self.params[self.cur_param].add_part();
@@ -478,6 +658,17 @@ impl Parser {
     }
 
     fn esc_dispatch(&mut self, input: char) -> Option<Function> {
+        let fun = self.esc_function(input);
+
+        // ESC \ (ST) terminates a string sequence, it's not unknown
+        if fun.is_none() && input != '\\' {
+            self.unknown();
+        }
+
+        fun
+    }
+
+    fn esc_function(&mut self, input: char) -> Option<Function> {
         use Function::*;
 
         match (self.intermediate, input) {
@@ -487,11 +678,19 @@ impl Parser {
 
             (None, '8') => Some(Decrc),
 
+            (None, '=') => Some(Deckpam),
+
+            (None, '>') => Some(Deckpnm),
+
             (None, 'c') => {
                 self.state = State::Ground;
                 Some(Ris)
             }
 
+            (Some(' '), 'F') => Some(S7c1t),
+
+            (Some(' '), 'G') => Some(S8c1t),
+
             (Some('#'), '8') => Some(Decaln),
 
             (Some('('), '0') => Some(Gzd4(Charset::Drawing)),
@@ -506,7 +705,103 @@ impl Parser {
         }
     }
 
+    fn vt52_advance(&mut self, input: char) -> Option<Function> {
+        use Vt52State::*;
+
+        match (self.vt52_state, input) {
+            (_, '\u{1b}') => {
+                self.vt52_state = Escape;
+
+                None
+            }
+
+            (_, '\u{18}') | (_, '\u{1a}') => {
+                self.vt52_state = Ground;
+
+                None
+            }
+
+            (_, '\u{00}'..='\u{1f}') => self.execute(input),
+
+            (Ground, '\u{7f}'..='\u{9f}') => None,
+
+            (Ground, _) => Some(Function::Print(input)),
+
+            (Escape, _) => {
+                self.vt52_state = Ground;
+
+                self.vt52_dispatch(input)
+            }
+
+            (Row, _) => {
+                self.vt52_state = Col(input);
+
+                None
+            }
+
+            (Col(row), col) => {
+                self.vt52_state = Ground;
+
+                Some(Function::Cup(vt52_coord(row), vt52_coord(col)))
+            }
+        }
+    }
+
+    fn vt52_dispatch(&mut self, input: char) -> Option<Function> {
+        use Function::*;
+
+        match input {
+            'A' => Some(Cuu(1)),
+            'B' => Some(Cud(1)),
+            'C' => Some(Cuf(1)),
+            'D' => Some(Cub(1)),
+            'F' => Some(Gzd4(Charset::Drawing)),
+            'G' => Some(Gzd4(Charset::Ascii)),
+            'H' => Some(Cup(1, 1)),
+            'I' => Some(Ri),
+            'J' => Some(Ed(EdScope::Below)),
+            'K' => Some(El(ElScope::ToRight)),
+            'Z' => Some(Vt52Identify),
+            '=' => Some(Deckpam),
+            '>' => Some(Deckpnm),
+
+            'Y' => {
+                self.vt52_state = Vt52State::Row;
+
+                None
+            }
+
+            '<' => {
+                self.vt52_mode = false;
+
+                None
+            }
+
+            _ => None,
+        }
+    }
+
+    pub fn vt52_mode(&self) -> bool {
+        self.vt52_mode
+    }
+
+    // DECRST ?2 (DECANM) switches the parser itself into VT52 mode
+    pub(crate) fn enter_vt52_mode(&mut self) {
+        self.vt52_mode = true;
+        self.vt52_state = Vt52State::Ground;
+    }
+
     fn csi_dispatch(&mut self, input: char) -> Option<Function> {
+        let fun = self.csi_function(input);
+
+        if fun.is_none() {
+            self.unknown();
+        }
+
+        fun
+    }
+
+    fn csi_function(&mut self, input: char) -> Option<Function> {
         use Function::*;
 
         let ps = &self.params;
@@ -586,55 +881,197 @@ impl Parser {
                 _ => None,
             },
 
-            (None, 'h') => Some(Sm(ps[..=self.cur_param]
-                .iter()
-                .filter_map(ansi_mode)
-                .collect())),
+            (None, 'h') => Some(Sm(self.modes(ansi_mode))),
 
-            (None, 'l') => Some(Rm(ps[..=self.cur_param]
-                .iter()
-                .filter_map(ansi_mode)
-                .collect())),
+            (None, 'l') => Some(Rm(self.modes(ansi_mode))),
 
             (None, 'm') => Some(Sgr(SgrOps {
                 ps: &ps[..=self.cur_param],
             }
             .collect())),
 
+            (None, 'n') => match ps[0].as_u16() {
+                5 => Some(Dsr(DsrOp::Status)),
+                6 => Some(Dsr(DsrOp::CursorPosition)),
+                _ => None,
+            },
+
             (None, 'r') => Some(Decstbm(ps[0].as_u16(), ps[1].as_u16())),
 
             (None, 's') => Some(Scosc),
 
-            (None, 't') => {
-                if ps[0].as_u16() == 8 {
+            (None, 't') => match ps[0].as_u16() {
+                8 => {
                     let rows = ps[1].as_u16();
                     let cols = ps[2].as_u16();
 
                     Some(Xtwinops(XtwinopsOp::Resize(cols, rows)))
-                } else {
-                    None
                 }
-            }
+
+                11 => Some(Xtwinops(XtwinopsOp::ReportWindowState)),
+                13 => Some(Xtwinops(XtwinopsOp::ReportWindowPosition)),
+                14 => Some(Xtwinops(XtwinopsOp::ReportPixelSize)),
+                18 => Some(Xtwinops(XtwinopsOp::ReportTextAreaSize)),
+                19 => Some(Xtwinops(XtwinopsOp::ReportScreenSize)),
+                _ => None,
+            },
 
             (None, 'u') => Some(Scorc),
 
             (Some('!'), 'p') => Some(Decstr),
 
-            (Some('?'), 'h') => Some(Decset(
-                ps[..=self.cur_param].iter().filter_map(dec_mode).collect(),
-            )),
+            (Some('?'), 'h') => Some(Decset(self.modes(dec_mode))),
 
-            (Some('?'), 'l') => Some(Decrst(
-                ps[..=self.cur_param].iter().filter_map(dec_mode).collect(),
-            )),
+            (Some('?'), 'l') => {
+                let modes = self.modes(dec_mode);
+
+                if modes.contains(&DecMode::Ansi) {
+                    self.enter_vt52_mode();
+                }
+
+                Some(Decrst(modes))
+            }
 
             _ => None,
         }
     }
 
-    fn put(&mut self, _input: char) {}
+    // unsupported modes are dropped, while the sequence as a whole is
+    // recorded as unknown
+    fn modes<T>(&mut self, mode: fn(&Param) -> Option<T>) -> Vec<T> {
+        let ps = &self.params[..=self.cur_param];
+        let modes: Vec<T> = ps.iter().filter_map(mode).collect();
+
+        if modes.len() < ps.len() {
+            self.unknown();
+        }
 
-    fn osc_put(&mut self, _input: char) {}
+        modes
+    }
+
+    // keeps the bytes of the ESC/CSI sequence being parsed, minus C0
+    // controls executed in the middle of it
+    fn record_raw(&mut self, state: State, input: char) {
+        use State::*;
+
+        match (state, input) {
+            (_, '\u{1b}') | (_, '\u{9b}') => {
+                self.raw_seq.clear();
+                self.raw_seq.push(input);
+            }
+
+            (_, '\u{00}'..='\u{1f}') | (_, '\u{7f}') => {}
+
+            (
+                Escape | EscapeIntermediate | CsiEntry | CsiParam | CsiIntermediate | CsiIgnore,
+                _,
+            ) if self.raw_seq.len() < self.max_string_len => {
+                self.raw_seq.push(input);
+            }
+
+            _ => {}
+        }
+    }
+
+    fn unknown(&mut self) {
+        if let Some(seqs) = &mut self.unknown_sequences {
+            seqs.push(self.raw_seq.clone());
+        }
+    }
+
+    fn csi_seq(&self) -> String {
+        let intermediates = self.intermediate.iter().collect::<String>();
+
+        let params = &self.params[..=self.cur_param]
+            .iter()
+            .map(|param| param.to_string())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        format!("\u{9b}{intermediates}{params}")
+    }
+
+    fn hook(&mut self, input: char) {
+        self.dcs_hook = match (self.intermediate, input) {
+            (None, 'q') => Some(DcsHook::Sixel),
+            (Some('$'), 'q') => Some(DcsHook::Decrqss),
+            _ => None,
+        };
+
+        self.dcs_data.clear();
+    }
+
+    fn put(&mut self, input: char) {
+        if self.dcs_hook.is_some() {
+            if self.dcs_data.len() < self.max_string_len {
+                self.dcs_data.push(input);
+            } else {
+                self.cancel_dcs();
+            }
+        }
+    }
+
+    fn cancel_dcs(&mut self) {
+        self.dcs_hook = None;
+        self.dcs_data.clear();
+    }
+
+    fn unhook(&mut self) -> Option<Function> {
+        match self.dcs_hook.take() {
+            Some(DcsHook::Sixel) => Some(Function::Sixel(mem::take(&mut self.dcs_data))),
+            Some(DcsHook::Decrqss) => Some(Function::Decrqss(mem::take(&mut self.dcs_data))),
+            None => None,
+        }
+    }
+
+    fn osc_start(&mut self) {
+        self.osc_data = Some(String::new());
+    }
+
+    fn osc_put(&mut self, input: char) {
+        if let Some(data) = &mut self.osc_data {
+            if data.len() < self.max_string_len {
+                data.push(input);
+            } else {
+                self.osc_data = None;
+            }
+        }
+    }
+
+    fn osc_end(&mut self) -> Option<Function> {
+        let data = self.osc_data.take()?;
+
+        match data.split_once(';') {
+            Some(("0" | "2", title)) => Some(Function::Osc(OscOp::SetTitle(title.to_owned()))),
+
+            // semantic prompt marks (FinalTerm / OSC 133)
+            Some(("133", mark)) => match mark.split(';').next() {
+                Some("A") => Some(Function::Osc(OscOp::PromptStart)),
+                Some("B") => Some(Function::Osc(OscOp::PromptEnd)),
+                _ => None,
+            },
+
+            _ => None,
+        }
+    }
+
+    fn apc_start(&mut self) {
+        self.apc_data = Some(String::new());
+    }
+
+    fn apc_put(&mut self, input: char) {
+        if let Some(data) = &mut self.apc_data {
+            if data.len() < self.max_string_len {
+                data.push(input);
+            } else {
+                self.apc_data = None;
+            }
+        }
+    }
+
+    fn apc_end(&mut self) -> Option<Function> {
+        self.apc_data.take().map(Function::Apc)
+    }
 
     pub(crate) fn dump(&self) -> String {
         use State::*;
@@ -659,16 +1096,11 @@ impl Parser {
             }
 
             CsiParam => {
-                let intermediates = self.intermediate.iter().collect::<String>();
+                seq.push_str(&self.csi_seq());
 
-                let params = &self.params[..=self.cur_param]
-                    .iter()
-                    .map(|param| param.to_string())
-                    .collect::<Vec<_>>()
-                    .join(";");
-
-                let s = &format!("\u{9b}{intermediates}{params}");
-                seq.push_str(s);
+                if self.params_overflow {
+                    seq.push(';');
+                }
             }
 
             CsiIntermediate => {
@@ -716,10 +1148,43 @@ impl Parser {
 
             OscString => {
                 seq.push('\u{9d}');
+
+                if let Some(data) = &self.osc_data {
+                    seq.push_str(data);
+                }
             }
 
-            SosPmApcString => {
-                seq.push('\u{98}');
+            SosPmApcString => match &self.apc_data {
+                Some(data) => {
+                    seq.push('\u{9f}');
+                    seq.push_str(data);
+                }
+
+                None => {
+                    seq.push('\u{98}');
+                }
+            },
+        }
+
+        if self.vt52_mode {
+            // enter VT52 mode
+            seq.push_str("\u{9b}?2l");
+
+            match self.vt52_state {
+                Vt52State::Ground => {}
+
+                Vt52State::Escape => {
+                    seq.push('\u{1b}');
+                }
+
+                Vt52State::Row => {
+                    seq.push_str("\u{1b}Y");
+                }
+
+                Vt52State::Col(row) => {
+                    seq.push_str("\u{1b}Y");
+                    seq.push(row);
+                }
             }
         }
 
@@ -731,11 +1196,21 @@ impl Parser {
         use State::*;
 
         assert_eq!(self.state, other.state);
+        assert_eq!(self.vt52_mode, other.vt52_mode);
+        assert_eq!(self.vt52_state, other.vt52_state);
 
         if self.state == CsiParam || self.state == DcsParam {
             assert_eq!(self.params, other.params);
         }
 
+        if self.state == CsiParam {
+            assert_eq!(self.params_overflow, other.params_overflow);
+        }
+
+        if self.state == SosPmApcString {
+            assert_eq!(self.apc_data, other.apc_data);
+        }
+
         if self.state == EscapeIntermediate
             || self.state == CsiIntermediate
             || self.state == CsiParam
@@ -747,6 +1222,10 @@ impl Parser {
     }
 }
 
+fn vt52_coord(input: char) -> u16 {
+    (input as u32).saturating_sub(0x1f).min(u16::MAX as u32) as u16
+}
+
 fn ansi_mode(param: &Param) -> Option<AnsiMode> {
     use AnsiMode::*;
 
@@ -793,12 +1272,24 @@ impl<'a> Iterator for SgrOps<'a> {
                     return Some(SetItalic);
                 }
 
-                [4] => {
+                [4] | [4, 1] | [4, 3..=5] => {
                     self.ps = &self.ps[1..];
 
                     return Some(SetUnderline);
                 }
 
+                [4, 2] => {
+                    self.ps = &self.ps[1..];
+
+                    return Some(SetDoubleUnderline);
+                }
+
+                [4, 0] => {
+                    self.ps = &self.ps[1..];
+
+                    return Some(ResetUnderline);
+                }
+
                 [5] => {
                     self.ps = &self.ps[1..];
 
@@ -817,7 +1308,13 @@ impl<'a> Iterator for SgrOps<'a> {
                     return Some(SetStrikethrough);
                 }
 
-                [21] | [22] => {
+                [21] => {
+                    self.ps = &self.ps[1..];
+
+                    return Some(SetDoubleUnderline);
+                }
+
+                [22] => {
                     self.ps = &self.ps[1..];
 
                     return Some(ResetIntensity);
@@ -888,7 +1385,7 @@ impl<'a> Iterator for SgrOps<'a> {
 
                             return Some(SetForegroundColor(color));
                         } else {
-                            self.ps = &self.ps[2..];
+                            self.ps = &[];
                         }
                     }
 
@@ -951,7 +1448,7 @@ impl<'a> Iterator for SgrOps<'a> {
 
                             return Some(SetBackgroundColor(color));
                         } else {
-                            self.ps = &self.ps[2..];
+                            self.ps = &[];
                         }
                     }
 
@@ -1007,18 +1504,33 @@ fn dec_mode(param: &Param) -> Option<DecMode> {
 
     match param.as_u16() {
         1 => Some(CursorKeys),
+        2 => Some(Ansi),
+        5 => Some(ReverseScreen),
         6 => Some(Origin),
         7 => Some(AutoWrap),
+        9 => Some(X10Mouse),
+        12 => Some(CursorBlink),
         25 => Some(TextCursorEnable),
+        80 => Some(SixelDisplay),
         47 => Some(AltScreenBuffer), // legacy variant of 1047
+        1000 => Some(NormalMouse),
+        1002 => Some(ButtonEventMouse),
+        1003 => Some(AnyEventMouse),
+        1004 => Some(FocusEvent),
+        1005 => Some(Utf8Mouse),
+        1006 => Some(SgrMouse),
+        1015 => Some(UrxvtMouse),
         1047 => Some(AltScreenBuffer),
         1048 => Some(SaveCursor),
         1049 => Some(SaveCursorAltScreenBuffer),
+        1070 => Some(PrivateColorRegisters),
+        2004 => Some(BracketedPaste),
         _ => None,
     }
 }
 
 const MAX_PARAM_LEN: usize = 6;
+pub(crate) const DEFAULT_MAX_STRING_LEN: usize = 64 * 1024;
 
 #[derive(Debug, PartialEq, Clone)]
 struct Param {
@@ -1045,7 +1557,7 @@ impl Param {
 
     pub fn add_digit(&mut self, input: u8) {
         let number = &mut self.parts[self.cur_part];
-        *number = (10 * (*number as u32) + (input as u32)) as u16;
+        *number = (10 * (*number as u32) + (input as u32)).min(u16::MAX as u32) as u16;
     }
 
     pub fn as_u16(&self) -> u16 {
@@ -1119,10 +1631,15 @@ impl PartialEq<Vec<u16>> for Param {
 mod tests {
     use super::AnsiMode;
     use super::DecMode;
+    use super::DsrOp;
+    use super::EdScope;
+    use super::ElScope;
     use super::Function;
     use super::Function::*;
+    use super::OscOp;
     use super::Parser;
     use super::SgrOp::*;
+    use super::XtwinopsOp;
     use crate::color::Color;
 
     fn parse(s: &str) -> Vec<Function> {
@@ -1133,8 +1650,11 @@ mod tests {
 
     #[test]
     fn parse_c0() {
+        assert_eq!(parse("\x07"), [Bel]);
         assert_eq!(parse("\x08"), [Bs]);
         assert_eq!(parse("\x0a"), [Lf]);
+        assert_eq!(parse("\x0b"), [Lf]);
+        assert_eq!(parse("\x0c"), [Ff]);
         assert_eq!(parse("\x0d"), [Cr]);
         assert_eq!(parse("\x0e"), [So]);
         assert_eq!(parse("\x0f"), [Si]);
@@ -1148,17 +1668,88 @@ mod tests {
         assert_eq!(parse("\u{8d}"), [Ri]);
     }
 
+    #[test]
+    fn parse_c1_vs_7bit() {
+        let cases = [
+            ("\x1bD", "\u{84}"),
+            ("\x1bE", "\u{85}"),
+            ("\x1bH", "\u{88}"),
+            ("\x1bM", "\u{8d}"),
+            ("\x1b[3;4H", "\u{9b}3;4H"),
+            ("\x1b[?25;7l", "\u{9b}?25;7l"),
+            ("\x1b[!p", "\u{9b}!p"),
+            ("\x1b[1;38:2:1:2:3m", "\u{9b}1;38:2:1:2:3m"),
+            ("\x1bPq#0;1\x1b\\a", "\u{90}q#0;1\u{9c}a"),
+            ("\x1bP1$r\x1b\\a", "\u{90}1$r\u{9c}a"),
+            ("\x1b]0;title\x1b\\a", "\u{9d}0;title\u{9c}a"),
+            ("\x1b]0;title\x07a", "\u{9d}0;title\x07a"),
+            ("\x1bXfoo\x1b\\a", "\u{98}foo\u{9c}a"),
+            ("\x1b^foo\x1b\\a", "\u{9e}foo\u{9c}a"),
+            ("\x1b_foo\x1b\\a", "\u{9f}foo\u{9c}a"),
+        ];
+
+        for (seq7, seq8) in cases {
+            let mut parser7 = Parser::new();
+            let mut parser8 = Parser::new();
+            let fns7: Vec<Function> = seq7.chars().filter_map(|ch| parser7.feed(ch)).collect();
+            let fns8: Vec<Function> = seq8.chars().filter_map(|ch| parser8.feed(ch)).collect();
+
+            assert_eq!(fns7, fns8, "{seq7:?} vs {seq8:?}");
+            assert_eq!(parser7.state, parser8.state, "{seq7:?} vs {seq8:?}");
+        }
+
+        for (open7, open8) in [
+            ("\x1b[", "\u{9b}"),
+            ("\x1bP", "\u{90}"),
+            ("\x1b]", "\u{9d}"),
+            ("\x1bX", "\u{98}"),
+            ("\x1b^", "\u{9e}"),
+            ("\x1b_", "\u{9f}"),
+        ] {
+            let mut parser7 = Parser::new();
+            let mut parser8 = Parser::new();
+            open7.chars().for_each(|ch| _ = parser7.feed(ch));
+            open8.chars().for_each(|ch| _ = parser8.feed(ch));
+
+            assert_eq!(parser7.state, parser8.state, "{open7:?} vs {open8:?}");
+        }
+    }
+
     #[test]
     fn parse_esc_seq() {
         assert_eq!(parse("\x1b7"), [Decsc]);
         assert_eq!(parse("\x1bc"), [Ris]);
         assert_eq!(parse("\x1bM"), [Ri]);
+        assert_eq!(parse("\x1b="), [Deckpam]);
+        assert_eq!(parse("\x1b>"), [Deckpnm]);
+        assert_eq!(parse("\x1b F"), [S7c1t]);
+        assert_eq!(parse("\x1b G"), [S8c1t]);
     }
 
     #[test]
     fn parse_csi_seq() {
         assert_eq!(parse("\x1b[@"), [Ich(0)]);
         assert_eq!(parse("\x1b[3;4H"), [Cup(3, 4)]);
+        assert_eq!(parse("\x1b[;5H"), [Cup(0, 5)]);
+        assert_eq!(parse("\x1b[5;H"), [Cup(5, 0)]);
+        assert_eq!(parse("\x1b[H"), [Cup(0, 0)]);
+        assert_eq!(parse("\x1b]133;A\x07"), [Osc(OscOp::PromptStart)]);
+        assert_eq!(parse("\x1b]133;B;x=1\x1b\\"), [Osc(OscOp::PromptEnd)]);
+        assert_eq!(parse("\x1b]133;C\x07"), []);
+        assert_eq!(
+            parse("\x1b[8;24;80t"),
+            [Xtwinops(XtwinopsOp::Resize(80, 24))]
+        );
+        assert_eq!(
+            parse("\x1b[18t"),
+            [Xtwinops(XtwinopsOp::ReportTextAreaSize)]
+        );
+        assert_eq!(parse("\x1b[14;2t"), [Xtwinops(XtwinopsOp::ReportPixelSize)]);
+        assert_eq!(parse("\x1b[99999999;65536H"), [Cup(65535, 65535)]);
+
+        let seq = format!("\x1b[{}{}m", "1;".repeat(32), "7;".repeat(8));
+
+        assert_eq!(parse(&seq), [Sgr(vec![SetBoldIntensity; 32])]);
 
         assert_eq!(
             parse("\x1b[4;20h"),
@@ -1170,7 +1761,18 @@ mod tests {
             [Decset(vec![DecMode::Origin, DecMode::AltScreenBuffer])]
         );
 
+        assert_eq!(
+            parse("\x1b[?1002;1006;2004h"),
+            [Decset(vec![
+                DecMode::ButtonEventMouse,
+                DecMode::SgrMouse,
+                DecMode::BracketedPaste
+            ])]
+        );
+
         assert_eq!(parse("\x1b[m"), [Sgr(vec![Reset])]);
+        assert_eq!(parse("\x1b[5n"), [Dsr(DsrOp::Status)]);
+        assert_eq!(parse("\x1b[6n"), [Dsr(DsrOp::CursorPosition)]);
     }
 
     #[test]
@@ -1184,10 +1786,17 @@ mod tests {
         assert_eq!(parse("\x1b[2m"), [Sgr(vec![SetFaintIntensity])]);
         assert_eq!(parse("\x1b[3m"), [Sgr(vec![SetItalic])]);
         assert_eq!(parse("\x1b[4m"), [Sgr(vec![SetUnderline])]);
+        assert_eq!(parse("\x1b[4:0m"), [Sgr(vec![ResetUnderline])]);
+        assert_eq!(parse("\x1b[4:1m"), [Sgr(vec![SetUnderline])]);
+        assert_eq!(parse("\x1b[4:2m"), [Sgr(vec![SetDoubleUnderline])]);
+        assert_eq!(parse("\x1b[4:3m"), [Sgr(vec![SetUnderline])]);
+        assert_eq!(parse("\x1b[4:4m"), [Sgr(vec![SetUnderline])]);
+        assert_eq!(parse("\x1b[4:5m"), [Sgr(vec![SetUnderline])]);
+        assert_eq!(parse("\x1b[4:6m"), [Sgr(vec![])]);
         assert_eq!(parse("\x1b[5m"), [Sgr(vec![SetBlink])]);
         assert_eq!(parse("\x1b[7m"), [Sgr(vec![SetInverse])]);
         assert_eq!(parse("\x1b[9m"), [Sgr(vec![SetStrikethrough])]);
-        assert_eq!(parse("\x1b[21m"), [Sgr(vec![ResetIntensity])]);
+        assert_eq!(parse("\x1b[21m"), [Sgr(vec![SetDoubleUnderline])]);
         assert_eq!(parse("\x1b[22m"), [Sgr(vec![ResetIntensity])]);
         assert_eq!(parse("\x1b[23m"), [Sgr(vec![ResetItalic])]);
         assert_eq!(parse("\x1b[24m"), [Sgr(vec![ResetUnderline])]);
@@ -1260,6 +1869,151 @@ mod tests {
                 Reset,
             ])]
         );
+
+        // colon syntax for 24-bit and 8-bit colors, within a larger sequence
+        assert_eq!(
+            parse("\x1b[1;38:2::1:2:3;48:5:99;4m"),
+            [Sgr(vec![
+                SetBoldIntensity,
+                SetForegroundColor(Color::rgb(1, 2, 3)),
+                SetBackgroundColor(Color::Indexed(99)),
+                SetUnderline,
+            ])]
+        );
+
+        assert_eq!(
+            parse("\x1b[1;38:2::1:2:3;48:5:99;4m"),
+            parse("\x1b[1;38;2;1;2;3;48;5;99;4m")
+        );
+
+        assert_eq!(
+            parse("\x1b[38:5:88;48:2:7:1:2:3;0m"),
+            parse("\x1b[38;5;88;48;2;1;2;3;0m")
+        );
+
+        // malformed 38/48 runs among other ops
+        assert_eq!(parse("\x1b[38m"), [Sgr(vec![])]);
+        assert_eq!(parse("\x1b[1;38m"), [Sgr(vec![SetBoldIntensity])]);
+        assert_eq!(parse("\x1b[38;m"), [Sgr(vec![Reset])]);
+        assert_eq!(parse("\x1b[48;5m"), [Sgr(vec![])]);
+        assert_eq!(parse("\x1b[3;48;5m"), [Sgr(vec![SetItalic])]);
+        assert_eq!(parse("\x1b[3;38;2;1;2m"), [Sgr(vec![SetItalic])]);
+        assert_eq!(parse("\x1b[3;48;2;1m"), [Sgr(vec![SetItalic])]);
+
+        assert_eq!(
+            parse("\x1b[38;7;1m"),
+            [Sgr(vec![SetInverse, SetBoldIntensity])]
+        );
+
+        assert_eq!(
+            parse("\x1b[38:2:1:2;4;48:5;9m"),
+            [Sgr(vec![SetUnderline, SetStrikethrough])]
+        );
+    }
+
+    #[test]
+    fn parse_dcs() {
+        assert_eq!(
+            parse("\x1bPq#0;2;0;0;0#0!10~-\x1b\\a"),
+            [Sixel("#0;2;0;0;0#0!10~-".to_owned()), Print('a')]
+        );
+
+        assert_eq!(
+            parse("\u{90}0;1;0q\"1;1;10;6#1~~\u{9c}"),
+            [Sixel("\"1;1;10;6#1~~".to_owned())]
+        );
+
+        assert_eq!(parse("\x1bP$qm\x1b\\"), [Decrqss("m".to_owned())]);
+        assert_eq!(parse("\u{90}$q q\u{9c}"), [Decrqss(" q".to_owned())]);
+        assert_eq!(parse("\x1bP1$r0m\x1b\\"), []);
+        assert_eq!(parse("\x1bPqabc\x18d"), [Print('d')]);
+        assert_eq!(parse("\x1bPqabc\x1ad"), [Print('d')]);
+        assert_eq!(parse("\x1bPqabc\u{9c}"), [Sixel("abc".to_owned())]);
+        assert_eq!(parse("\x1bPqab\x1b[1m"), [Sgr(vec![SetBoldIntensity])]);
+        assert_eq!(parse("\x1bP$qm\x1b\x1b\\"), []);
+    }
+
+    #[test]
+    fn parse_osc() {
+        assert_eq!(
+            parse("\x1b]0;t\u{ed}tle\x1b\\a"),
+            [Osc(OscOp::SetTitle("t\u{ed}tle".to_owned())), Print('a')]
+        );
+
+        assert_eq!(
+            parse("\u{9d}2;foo;bar\x07"),
+            [Osc(OscOp::SetTitle("foo;bar".to_owned()))]
+        );
+
+        assert_eq!(parse("\x1b]1;icon\x07"), []);
+        assert_eq!(parse("\x1b]8;;http://example.com\x07"), []);
+    }
+
+    #[test]
+    fn parse_apc() {
+        assert_eq!(
+            parse("\x1b_Ga=T;AAAA\x1b\\a"),
+            [Apc("Ga=T;AAAA".to_owned()), Print('a')]
+        );
+
+        assert_eq!(parse("\u{9f}foo\u{9c}"), [Apc("foo".to_owned())]);
+        assert_eq!(parse("\x1bXfoo\x1b\\\x1b^bar\x1b\\"), []);
+        assert_eq!(parse("\x1b_Gabc\x18d"), [Print('d')]);
+        assert_eq!(parse("\x1b_Gabc\x1ad"), [Print('d')]);
+        assert_eq!(parse("\x1b_Gabc\u{85}"), [Nel]);
+        assert_eq!(parse("\x1b_Gabc\x1b[1m"), [Sgr(vec![SetBoldIntensity])]);
+
+        let long = format!(
+            "\x1b_{}\x1b\\a",
+            "x".repeat(super::DEFAULT_MAX_STRING_LEN + 1)
+        );
+
+        assert_eq!(parse(&long), [Print('a')]);
+    }
+
+    #[test]
+    fn parse_vt52() {
+        let mut parser = Parser::new();
+
+        let mut parse =
+            |s: &str| -> Vec<Function> { s.chars().filter_map(|ch| parser.feed(ch)).collect() };
+
+        assert_eq!(parse("\x1b[?2l"), [Decrst(vec![DecMode::Ansi])]);
+
+        assert_eq!(
+            parse("a\x1bA\x1bD\x1bH\x1bJ\x1bK\x1bY#*\r"),
+            [
+                Print('a'),
+                Cuu(1),
+                Cub(1),
+                Cup(1, 1),
+                Ed(EdScope::Below),
+                El(ElScope::ToRight),
+                Cup(4, 11),
+                Cr
+            ]
+        );
+
+        assert_eq!(parse("\x1bZ\x1b[A"), [Vt52Identify, Print('A')]);
+        assert_eq!(parse("\x1b<\x1b[A"), [Cuu(0)]);
+    }
+
+    #[test]
+    fn max_string_len() {
+        let mut parser = Parser::with_max_string_len(4);
+
+        let mut parse =
+            |s: &str| -> Vec<Function> { s.chars().filter_map(|ch| parser.feed(ch)).collect() };
+
+        assert_eq!(
+            parse("\x1b]2;ab\x07"),
+            [Osc(OscOp::SetTitle("ab".to_owned()))]
+        );
+
+        assert_eq!(parse("\x1b]2;abc\x07a"), [Print('a')]);
+        assert_eq!(parse("\x1bPqabcd\x1b\\"), [Sixel("abcd".to_owned())]);
+        assert_eq!(parse("\x1bPqabcde\x1b\\a"), [Print('a')]);
+        assert_eq!(parse("\x1b_abcde\x1b\\a"), [Print('a')]);
     }
 
     #[test]
@@ -1271,5 +2025,19 @@ mod tests {
         }
 
         assert_eq!(parser.dump(), "\u{9b}0;1;0;38:2:1:2:3;0");
+
+        let mut parser = Parser::new();
+
+        for ch in format!("\x1b[{}9;9", "1;".repeat(32)).chars() {
+            parser.feed(ch);
+        }
+
+        let mut parser2 = Parser::new();
+
+        for ch in parser.dump().chars() {
+            parser2.feed(ch);
+        }
+
+        parser.assert_eq(&parser2);
     }
 }
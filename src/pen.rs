@@ -1,6 +1,7 @@
 use crate::color::Color;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pen {
     pub(crate) foreground: Option<Color>,
     pub(crate) background: Option<Color>,
@@ -8,7 +9,8 @@ pub struct Pen {
     pub(crate) attrs: u8,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Intensity {
     Normal,
     Bold,
@@ -20,6 +22,12 @@ const UNDERLINE_MASK: u8 = 1 << 1;
 const STRIKETHROUGH_MASK: u8 = 1 << 2;
 const BLINK_MASK: u8 = 1 << 3;
 const INVERSE_MASK: u8 = 1 << 4;
+const DOUBLE_UNDERLINE_MASK: u8 = 1 << 5;
+const UNSELECTABLE_MASK: u8 = 1 << 6;
+
+// OSC 133 marks delimiting non-selectable (prompt) text in dumps
+pub(crate) const PROMPT_START: &str = "\u{9d}133;A\u{9c}";
+pub(crate) const PROMPT_END: &str = "\u{9d}133;B\u{9c}";
 
 impl Pen {
     pub fn foreground(&self) -> Option<Color> {
@@ -30,6 +38,14 @@ impl Pen {
         self.background
     }
 
+    pub fn intensity(&self) -> Intensity {
+        self.intensity
+    }
+
+    pub fn attr_bits(&self) -> u8 {
+        self.attrs
+    }
+
     pub fn is_bold(&self) -> bool {
         self.intensity == Intensity::Bold
     }
@@ -46,6 +62,10 @@ impl Pen {
         (self.attrs & UNDERLINE_MASK) != 0
     }
 
+    pub fn is_double_underline(&self) -> bool {
+        (self.attrs & DOUBLE_UNDERLINE_MASK) != 0
+    }
+
     pub fn is_strikethrough(&self) -> bool {
         (self.attrs & STRIKETHROUGH_MASK) != 0
     }
@@ -58,14 +78,24 @@ impl Pen {
         (self.attrs & INVERSE_MASK) != 0
     }
 
+    pub fn is_selectable(&self) -> bool {
+        (self.attrs & UNSELECTABLE_MASK) == 0
+    }
+
     pub fn set_italic(&mut self) {
         self.attrs |= ITALIC_MASK;
     }
 
     pub fn set_underline(&mut self) {
+        self.attrs &= !DOUBLE_UNDERLINE_MASK;
         self.attrs |= UNDERLINE_MASK;
     }
 
+    pub fn set_double_underline(&mut self) {
+        self.attrs &= !UNDERLINE_MASK;
+        self.attrs |= DOUBLE_UNDERLINE_MASK;
+    }
+
     pub fn set_blink(&mut self) {
         self.attrs |= BLINK_MASK;
     }
@@ -78,12 +108,16 @@ impl Pen {
         self.attrs |= INVERSE_MASK;
     }
 
+    pub fn set_unselectable(&mut self) {
+        self.attrs |= UNSELECTABLE_MASK;
+    }
+
     pub fn unset_italic(&mut self) {
         self.attrs &= !ITALIC_MASK;
     }
 
     pub fn unset_underline(&mut self) {
-        self.attrs &= !UNDERLINE_MASK;
+        self.attrs &= !(UNDERLINE_MASK | DOUBLE_UNDERLINE_MASK);
     }
 
     pub fn unset_blink(&mut self) {
@@ -104,13 +138,19 @@ impl Pen {
             && self.intensity == Intensity::Normal
             && !self.is_italic()
             && !self.is_underline()
+            && !self.is_double_underline()
             && !self.is_strikethrough()
             && !self.is_blink()
             && !self.is_inverse()
+            && self.is_selectable()
     }
 
     pub(crate) fn dump(&self) -> String {
-        let mut s = "\x1b[0".to_owned();
+        format!("\x1b[{}m", self.sgr_params())
+    }
+
+    pub(crate) fn sgr_params(&self) -> String {
+        let mut s = "0".to_owned();
 
         if let Some(c) = self.foreground {
             s.push_str(&format!(";{}", c.sgr_params(30)));
@@ -152,10 +192,87 @@ impl Pen {
             s.push_str(";9");
         }
 
-        s.push('m');
+        if self.is_double_underline() {
+            s.push_str(";21");
+        }
 
         s
     }
+
+    // minimal SGR sequence turning `from` into self, falling back to a full
+    // reset when that's shorter
+    pub fn sgr_diff(&self, from: &Pen) -> String {
+        let mut params: Vec<String> = Vec::new();
+
+        if self.foreground != from.foreground {
+            params.push(match self.foreground {
+                Some(c) => c.sgr_params(30),
+                None => "39".to_owned(),
+            });
+        }
+
+        if self.background != from.background {
+            params.push(match self.background {
+                Some(c) => c.sgr_params(40),
+                None => "49".to_owned(),
+            });
+        }
+
+        // bold and faint are independent flags in most terminals, so the old
+        // weight has to be reset before setting the new one
+        if self.intensity != from.intensity {
+            if self.intensity == Intensity::Normal || from.intensity != Intensity::Normal {
+                params.push("22".to_owned());
+            }
+
+            match self.intensity {
+                Intensity::Normal => (),
+                Intensity::Bold => params.push("1".to_owned()),
+                Intensity::Faint => params.push("2".to_owned()),
+            }
+        }
+
+        let flags = [
+            (self.is_italic(), from.is_italic(), "3", "23"),
+            (self.is_blink(), from.is_blink(), "5", "25"),
+            (self.is_inverse(), from.is_inverse(), "7", "27"),
+            (self.is_strikethrough(), from.is_strikethrough(), "9", "29"),
+        ];
+
+        for (on, was_on, set, unset) in flags {
+            if on != was_on {
+                params.push(if on { set } else { unset }.to_owned());
+            }
+        }
+
+        let underline = (self.is_underline(), self.is_double_underline());
+        let was_underline = (from.is_underline(), from.is_double_underline());
+
+        if underline != was_underline {
+            if underline == (false, false) || was_underline != (false, false) {
+                params.push("24".to_owned());
+            }
+
+            match underline {
+                (true, _) => params.push("4".to_owned()),
+                (_, true) => params.push("21".to_owned()),
+                _ => (),
+            }
+        }
+
+        if params.is_empty() {
+            return String::new();
+        }
+
+        let delta = params.join(";");
+        let full = self.sgr_params();
+
+        if delta.len() < full.len() {
+            format!("\x1b[{delta}m")
+        } else {
+            format!("\x1b[{full}m")
+        }
+    }
 }
 
 impl Default for Pen {
@@ -168,3 +285,110 @@ impl Default for Pen {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Intensity, Pen};
+    use crate::color::Color;
+    use crate::vt::Vt;
+
+    fn pen(f: impl FnOnce(&mut Pen)) -> Pen {
+        let mut pen = Pen::default();
+        f(&mut pen);
+
+        pen
+    }
+
+    fn apply(from: &Pen, to: &Pen) -> Pen {
+        let mut vt = Vt::new(2, 1);
+        vt.feed_str(&format!("{}{}x", from.dump(), to.sgr_diff(from)));
+
+        *vt.line(0).cells()[0].pen()
+    }
+
+    #[test]
+    fn sgr_diff() {
+        let default = Pen::default();
+        let bold = pen(|p| p.intensity = Intensity::Bold);
+        let faint = pen(|p| p.intensity = Intensity::Faint);
+        let italic = pen(|p| p.set_italic());
+        let underline = pen(|p| p.set_underline());
+        let double_underline = pen(|p| p.set_double_underline());
+
+        let red_bold_italic = pen(|p| {
+            p.foreground = Some(Color::Indexed(1));
+            p.intensity = Intensity::Bold;
+            p.set_italic();
+        });
+
+        let red_italic = pen(|p| {
+            p.foreground = Some(Color::Indexed(1));
+            p.set_italic();
+        });
+
+        let red_faint_italic = pen(|p| {
+            p.foreground = Some(Color::Indexed(1));
+            p.intensity = Intensity::Faint;
+            p.set_italic();
+        });
+
+        let red_underline = pen(|p| {
+            p.foreground = Some(Color::Indexed(1));
+            p.set_underline();
+        });
+
+        let red_double_underline = pen(|p| {
+            p.foreground = Some(Color::Indexed(1));
+            p.set_double_underline();
+        });
+
+        assert_eq!(default.sgr_diff(&default), "");
+        assert_eq!(bold.sgr_diff(&default), "\x1b[1m");
+        assert_eq!(default.sgr_diff(&bold), "\x1b[0m");
+        assert_eq!(italic.sgr_diff(&bold), "\x1b[0;3m");
+        assert_eq!(double_underline.sgr_diff(&underline), "\x1b[0;21m");
+        assert_eq!(underline.sgr_diff(&double_underline), "\x1b[0;4m");
+        assert_eq!(red_italic.sgr_diff(&red_bold_italic), "\x1b[22m");
+        assert_eq!(red_bold_italic.sgr_diff(&italic), "\x1b[31;1m");
+        assert_eq!(faint.sgr_diff(&default), "\x1b[2m");
+        assert_eq!(faint.sgr_diff(&bold), "\x1b[0;2m");
+        assert_eq!(red_faint_italic.sgr_diff(&red_bold_italic), "\x1b[22;2m");
+        assert_eq!(red_bold_italic.sgr_diff(&red_faint_italic), "\x1b[22;1m");
+        assert_eq!(red_double_underline.sgr_diff(&red_underline), "\x1b[24;21m");
+        assert_eq!(red_underline.sgr_diff(&red_double_underline), "\x1b[24;4m");
+        assert_eq!(red_italic.sgr_diff(&red_underline), "\x1b[3;24m");
+
+        let pens = [
+            default,
+            bold,
+            faint,
+            italic,
+            underline,
+            double_underline,
+            red_bold_italic,
+            red_italic,
+            red_faint_italic,
+            red_underline,
+            red_double_underline,
+        ];
+
+        for from in &pens {
+            for to in &pens {
+                assert_eq!(apply(from, to), *to);
+            }
+        }
+    }
+
+    #[test]
+    fn intensity() {
+        assert_eq!(Pen::default().intensity(), Intensity::Normal);
+        assert_eq!(
+            pen(|p| p.intensity = Intensity::Bold).intensity(),
+            Intensity::Bold
+        );
+        assert_eq!(
+            pen(|p| p.intensity = Intensity::Faint).intensity(),
+            Intensity::Faint
+        );
+    }
+}
@@ -1,5 +1,10 @@
 #[derive(Debug, Clone)]
-pub(crate) struct Tabs(Vec<usize>);
+pub(crate) struct Tabs {
+    stops: Vec<usize>,
+    // true once the app has set or cleared any stop, in which case resizing
+    // wider doesn't add default stops in the new columns
+    custom: bool,
+}
 
 impl Tabs {
     pub fn new(cols: usize) -> Self {
@@ -9,40 +14,57 @@ impl Tabs {
             tabs.push(t);
         }
 
-        Tabs(tabs)
+        Tabs {
+            stops: tabs,
+            custom: false,
+        }
+    }
+
+    pub fn is_custom(&self) -> bool {
+        self.custom
     }
 
     pub fn set(&mut self, pos: usize) {
-        if let Err(index) = self.0.binary_search(&pos) {
-            self.0.insert(index, pos);
+        self.custom = true;
+
+        if let Err(index) = self.stops.binary_search(&pos) {
+            self.stops.insert(index, pos);
         }
     }
 
     pub fn unset(&mut self, pos: usize) {
-        if let Ok(index) = self.0.binary_search(&pos) {
-            self.0.remove(index);
+        self.custom = true;
+
+        if let Ok(index) = self.stops.binary_search(&pos) {
+            self.stops.remove(index);
         }
     }
 
-    pub fn expand(&mut self, mut start: usize, end: usize) {
-        start += 8 - start % 8;
+    pub fn expand(&mut self, start: usize, end: usize) {
+        if self.custom {
+            return;
+        }
+
+        // first multiple of 8 at or past the old width
+        let start = ((start + 7) / 8 * 8).max(8);
 
         for t in (start..end).step_by(8) {
-            self.0.push(t);
+            self.stops.push(t);
         }
     }
 
     pub fn contract(&mut self, pos: usize) {
-        let index = self.0.partition_point(|t| t < &pos);
-        self.0.truncate(index);
+        let index = self.stops.partition_point(|t| t < &pos);
+        self.stops.truncate(index);
     }
 
     pub fn clear(&mut self) {
-        self.0.clear();
+        self.custom = true;
+        self.stops.clear();
     }
 
     pub fn before(&self, pos: usize, n: usize) -> Option<usize> {
-        self.0
+        self.stops
             .iter()
             .rev()
             .skip_while(|t| pos <= **t)
@@ -51,7 +73,11 @@ impl Tabs {
     }
 
     pub fn after(&self, pos: usize, n: usize) -> Option<usize> {
-        self.0.iter().skip_while(|t| pos >= **t).nth(n - 1).copied()
+        self.stops
+            .iter()
+            .skip_while(|t| pos >= **t)
+            .nth(n - 1)
+            .copied()
     }
 }
 
@@ -60,19 +86,19 @@ impl<'a> IntoIterator for &'a Tabs {
     type IntoIter = std::slice::Iter<'a, usize>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.0.iter()
+        self.stops.iter()
     }
 }
 
 impl PartialEq for Tabs {
     fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+        self.stops == other.stops && self.custom == other.custom
     }
 }
 
 impl PartialEq<Vec<usize>> for Tabs {
     fn eq(&self, other: &Vec<usize>) -> bool {
-        &self.0 == other
+        &self.stops == other
     }
 }
 
@@ -88,4 +114,31 @@ mod tests {
         assert_eq!(Tabs::new(16), vec![8]);
         assert_eq!(Tabs::new(17), vec![8, 16]);
     }
+
+    #[test]
+    fn expand() {
+        let mut tabs = Tabs::new(10);
+        tabs.expand(10, 30);
+
+        assert_eq!(tabs, vec![8, 16, 24]);
+
+        let mut tabs = Tabs::new(16);
+        tabs.expand(16, 30);
+
+        assert_eq!(tabs, vec![8, 16, 24]);
+
+        let mut tabs = Tabs::new(10);
+        tabs.clear();
+        tabs.set(3);
+        tabs.expand(10, 30);
+
+        assert_eq!(tabs, vec![3]);
+
+        let mut tabs = Tabs::new(20);
+        tabs.unset(8);
+        tabs.contract(10);
+        tabs.expand(10, 30);
+
+        assert_eq!(tabs, vec![]);
+    }
 }
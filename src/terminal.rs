@@ -1,18 +1,23 @@
 mod cursor;
 mod dirty_lines;
+mod input_modes;
+mod sixel;
 pub use self::cursor::Cursor;
 use self::dirty_lines::DirtyLines;
+pub use self::input_modes::{InputModes, MouseEncoding, MouseMode};
+pub use self::sixel::Sixel;
 use crate::buffer::{Buffer, EraseMode};
-use crate::cell::Cell;
+use crate::cell::{self, Cell, WidthMode};
 use crate::charset::Charset;
 use crate::line::Line;
 use crate::parser::{
-    AnsiMode, CtcOp, DecMode, EdScope, ElScope, Function, SgrOp, TbcScope, XtwinopsOp,
+    AnsiMode, CtcOp, DecMode, DsrOp, EdScope, ElScope, Function, OscOp, SgrOp, TbcScope, XtwinopsOp,
 };
-use crate::pen::{Intensity, Pen};
+use crate::pen::{Intensity, Pen, PROMPT_START};
 use crate::tabs::Tabs;
 use std::cmp::Ordering;
 use std::mem;
+use std::ops::Range;
 
 #[derive(Debug)]
 pub(crate) struct Terminal {
@@ -22,6 +27,8 @@ pub(crate) struct Terminal {
     other_buffer: Buffer,
     active_buffer_type: BufferType,
     scrollback_limit: Option<usize>,
+    scrollback_slack: bool,
+    scrollback_reserve: usize,
     cursor: Cursor,
     pen: Pen,
     charsets: [Charset; 2],
@@ -30,16 +37,42 @@ pub(crate) struct Terminal {
     insert_mode: bool,
     origin_mode: bool,
     auto_wrap_mode: bool,
+    reverse_screen: bool,
+    cursor_blink: bool,
+    sixel_display_mode: bool,
+    private_color_registers: bool,
     new_line_mode: bool,
     cursor_keys_mode: CursorKeysMode,
+    keypad_app_mode: bool,
+    send_8bit_controls: bool,
+    bracketed_paste: bool,
+    focus_reporting: bool,
+    mouse_mode: MouseMode,
+    mouse_encoding: MouseEncoding,
     next_print_wraps: bool,
     top_margin: usize,
     bottom_margin: usize,
     saved_ctx: SavedCtx,
     alternate_saved_ctx: SavedCtx,
+    sco_saved_ctx: SavedCtx,
+    selectable: bool,
     dirty_lines: DirtyLines,
+    sixels: Vec<Sixel>,
+    title: String,
+    title_changed: bool,
+    bell: bool,
+    buffer_transitions: Vec<BufferTransition>,
+    cleared_scrollback: Vec<Line>,
+    apc_strings: Option<Vec<String>>,
     pub resizable: bool,
+    form_feed_clears: bool,
+    answerback: String,
+    cell_size: (usize, usize),
+    width_mode: WidthMode,
+    bce: bool,
     resized: bool,
+    generation: u64,
+    response: String,
 }
 
 #[derive(Debug, PartialEq)]
@@ -48,19 +81,27 @@ enum BufferType {
     Alternate,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BufferTransition {
+    EnteredAlternate,
+    LeftAlternate,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum CursorKeysMode {
     Normal,
     Application,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SavedCtx {
     pub cursor_col: usize,
     pub cursor_row: usize,
     pub pen: Pen,
     pub origin_mode: bool,
     pub auto_wrap_mode: bool,
+    pub charsets: [Charset; 2],
+    pub active_charset: usize,
 }
 
 impl Default for SavedCtx {
@@ -71,6 +112,8 @@ impl Default for SavedCtx {
             pen: Pen::default(),
             origin_mode: false,
             auto_wrap_mode: true,
+            charsets: [Charset::Ascii, Charset::Ascii],
+            active_charset: 0,
         }
     }
 }
@@ -81,7 +124,11 @@ impl Terminal {
         scrollback_limit: Option<usize>,
         resizable: bool,
     ) -> Self {
-        let primary_buffer = Buffer::new(cols, rows, scrollback_limit, None);
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+
+        let primary_buffer = primary_buffer(cols, rows, scrollback_limit, true, 0);
+
         let alternate_buffer = Buffer::new(cols, rows, Some(0), None);
         let dirty_lines = DirtyLines::new(rows);
 
@@ -92,6 +139,8 @@ impl Terminal {
             other_buffer: alternate_buffer,
             active_buffer_type: BufferType::Primary,
             scrollback_limit,
+            scrollback_slack: true,
+            scrollback_reserve: 0,
             tabs: Tabs::new(cols),
             cursor: Cursor::default(),
             pen: Pen::default(),
@@ -100,23 +149,65 @@ impl Terminal {
             insert_mode: false,
             origin_mode: false,
             auto_wrap_mode: true,
+            reverse_screen: false,
+            cursor_blink: false,
+            sixel_display_mode: false,
+            private_color_registers: true,
             new_line_mode: false,
             cursor_keys_mode: CursorKeysMode::Normal,
+            keypad_app_mode: false,
+            send_8bit_controls: false,
+            bracketed_paste: false,
+            focus_reporting: false,
+            mouse_mode: MouseMode::Off,
+            mouse_encoding: MouseEncoding::Default,
             next_print_wraps: false,
             top_margin: 0,
             bottom_margin: (rows - 1),
             saved_ctx: SavedCtx::default(),
             alternate_saved_ctx: SavedCtx::default(),
+            sco_saved_ctx: SavedCtx::default(),
+            selectable: true,
             dirty_lines,
+            sixels: Vec::new(),
+            title: String::new(),
+            title_changed: false,
+            bell: false,
+            buffer_transitions: Vec::new(),
+            cleared_scrollback: Vec::new(),
+            apc_strings: None,
             resizable,
+            form_feed_clears: false,
+            answerback: String::new(),
+            cell_size: (8, 16),
+            width_mode: WidthMode::Standard,
+            bce: true,
             resized: false,
+            generation: 0,
+            response: String::new(),
         }
     }
 
     pub fn execute(&mut self, fun: Function) {
         use Function::*;
 
+        if !matches!(
+            fun,
+            Apc(_) | Bel | Decrqss(_) | Dsr(_) | Enq | Vt52Identify | Xtwinops(_)
+        ) || matches!(fun, Xtwinops(XtwinopsOp::Resize(..)))
+        {
+            self.generation += 1;
+        }
+
         match fun {
+            Apc(data) => {
+                self.apc(data);
+            }
+
+            Bel => {
+                self.bel();
+            }
+
             Bs => {
                 self.bs();
             }
@@ -177,10 +268,22 @@ impl Terminal {
                 self.decaln();
             }
 
+            Deckpam => {
+                self.deckpam();
+            }
+
+            Deckpnm => {
+                self.deckpnm();
+            }
+
             Decrc => {
                 self.rc();
             }
 
+            Decrqss(setting) => {
+                self.decrqss(setting);
+            }
+
             Decrst(modes) => {
                 self.decrst(modes);
             }
@@ -205,6 +308,10 @@ impl Terminal {
                 self.dl(n);
             }
 
+            Dsr(op) => {
+                self.dsr(op);
+            }
+
             Ech(n) => {
                 self.ech(n);
             }
@@ -217,6 +324,14 @@ impl Terminal {
                 self.el(mode);
             }
 
+            Enq => {
+                self.enq();
+            }
+
+            Ff => {
+                self.ff();
+            }
+
             G1d4(charset) => {
                 self.g1d4(charset);
             }
@@ -249,6 +364,10 @@ impl Terminal {
                 self.nel();
             }
 
+            Osc(op) => {
+                self.osc(op);
+            }
+
             Print(ch) => {
                 self.print(ch);
             }
@@ -269,12 +388,20 @@ impl Terminal {
                 self.rm(modes);
             }
 
+            S7c1t => {
+                self.s7c1t();
+            }
+
+            S8c1t => {
+                self.s8c1t();
+            }
+
             Scorc => {
-                self.rc();
+                self.scorc();
             }
 
             Scosc => {
-                self.sc();
+                self.scosc();
             }
 
             Sd(n) => {
@@ -289,6 +416,10 @@ impl Terminal {
                 self.si();
             }
 
+            Sixel(data) => {
+                self.sixel(data);
+            }
+
             Sm(modes) => {
                 self.sm(modes);
             }
@@ -313,6 +444,10 @@ impl Terminal {
                 self.vpr(n);
             }
 
+            Vt52Identify => {
+                self.vt52_identify();
+            }
+
             Xtwinops(op) => {
                 self.xtwinops(op);
             }
@@ -323,16 +458,74 @@ impl Terminal {
         self.cursor
     }
 
+    pub fn set_cell_size(&mut self, width: usize, height: usize) {
+        self.cell_size = (width, height);
+    }
+
+    pub fn set_width_mode(&mut self, mode: WidthMode) {
+        self.width_mode = mode;
+    }
+
+    #[cfg(feature = "binary")]
+    pub(crate) fn width_mode(&self) -> WidthMode {
+        self.width_mode
+    }
+
+    pub fn set_bce(&mut self, bce: bool) {
+        self.bce = bce;
+    }
+
+    pub fn set_scrollback_slack(&mut self, slack: bool) {
+        self.scrollback_slack = slack;
+        let limit = self.scrollback_limit;
+
+        self.primary_buffer_mut()
+            .set_scrollback_limit(limit, slack)
+            .for_each(drop);
+    }
+
+    // pre-allocates room for scrollback lines, only with unlimited scrollback
+    pub fn set_scrollback_reserve(&mut self, reserve: usize) {
+        self.scrollback_reserve = reserve;
+
+        if self.scrollback_limit.is_none() {
+            self.primary_buffer_mut().reserve_scrollback(reserve);
+        }
+    }
+
+    pub fn set_form_feed_clears(&mut self, clears: bool) {
+        self.form_feed_clears = clears;
+    }
+
+    pub fn set_answerback(&mut self, answerback: String) {
+        self.answerback = answerback;
+    }
+
+    // APC payloads are only kept when asked for, so nothing piles up for
+    // callers that never take them
+    pub fn set_collect_apc_strings(&mut self, enabled: bool) {
+        self.apc_strings = enabled.then(Vec::new);
+    }
+
+    // start out on the alternate screen, as if it had been entered before
+    // any input
+    pub fn start_on_alternate_screen(&mut self) {
+        self.switch_to_alternate_buffer();
+        self.buffer_transitions.clear();
+    }
+
     pub fn gc(&mut self) -> Box<dyn Iterator<Item = Line> + '_> {
+        // lines removed by ED 3 come first, they left before any trimming
+        let cleared = mem::take(&mut self.cleared_scrollback).into_iter();
         let lines = self.buffer.gc();
 
         if self.active_buffer_type == BufferType::Alternate {
-            return Box::new(std::iter::empty());
+            return Box::new(cleared);
         }
 
         match lines {
-            Some(iter) => Box::new(iter),
-            None => Box::new(std::iter::empty()),
+            Some(iter) => Box::new(cleared.chain(iter)),
+            None => Box::new(cleared),
         }
     }
 
@@ -344,22 +537,128 @@ impl Terminal {
         changes
     }
 
+    pub fn set_scrollback_limit(
+        &mut self,
+        limit: Option<usize>,
+    ) -> impl Iterator<Item = Line> + '_ {
+        self.scrollback_limit = limit;
+        let slack = self.scrollback_slack;
+
+        self.primary_buffer_mut().set_scrollback_limit(limit, slack)
+    }
+
+    pub fn touch_all(&mut self) {
+        self.generation += 1;
+        self.dirty_lines.extend(0..self.rows);
+    }
+
+    // the alternate buffer has no scrollback, so its count is discarded
+    pub fn take_scrolled_lines(&mut self) -> usize {
+        self.alternate_buffer_mut().take_scrolled_lines();
+
+        self.primary_buffer_mut().take_scrolled_lines()
+    }
+
+    pub fn scrollback_len(&self) -> usize {
+        self.primary_buffer().scrollback_len()
+    }
+
+    fn respond(&mut self, seq: &str) {
+        self.response.push_str(seq);
+    }
+
+    // reply introducers and terminator, in 8-bit form after S8C1T
+
+    fn csi(&self) -> &'static str {
+        if self.send_8bit_controls {
+            "\u{9b}"
+        } else {
+            "\x1b["
+        }
+    }
+
+    fn dcs(&self) -> &'static str {
+        if self.send_8bit_controls {
+            "\u{90}"
+        } else {
+            "\x1bP"
+        }
+    }
+
+    fn st(&self) -> &'static str {
+        if self.send_8bit_controls {
+            "\u{9c}"
+        } else {
+            "\x1b\\"
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn take_title_changed(&mut self) -> bool {
+        mem::take(&mut self.title_changed)
+    }
+
+    pub fn take_bell(&mut self) -> bool {
+        mem::take(&mut self.bell)
+    }
+
+    pub fn is_alternate_buffer(&self) -> bool {
+        self.active_buffer_type == BufferType::Alternate
+    }
+
+    pub fn take_buffer_transitions(&mut self) -> Vec<BufferTransition> {
+        mem::take(&mut self.buffer_transitions)
+    }
+
+    pub fn take_apc_strings(&mut self) -> Vec<String> {
+        self.apc_strings.as_mut().map(mem::take).unwrap_or_default()
+    }
+
+    pub fn take_response(&mut self) -> Option<String> {
+        if self.response.is_empty() {
+            None
+        } else {
+            Some(mem::take(&mut self.response))
+        }
+    }
+
     // cursor
 
     fn save_cursor(&mut self) {
-        self.saved_ctx.cursor_col = self.cursor.col.min(self.cols - 1);
-        self.saved_ctx.cursor_row = self.cursor.row;
-        self.saved_ctx.pen = self.pen;
-        self.saved_ctx.origin_mode = self.origin_mode;
-        self.saved_ctx.auto_wrap_mode = self.auto_wrap_mode;
+        self.saved_ctx = self.cursor_ctx();
     }
 
     fn restore_cursor(&mut self) {
-        self.cursor.col = self.saved_ctx.cursor_col;
-        self.cursor.row = self.saved_ctx.cursor_row;
-        self.pen = self.saved_ctx.pen;
-        self.origin_mode = self.saved_ctx.origin_mode;
-        self.auto_wrap_mode = self.saved_ctx.auto_wrap_mode;
+        self.restore_ctx(self.saved_ctx.clone());
+    }
+
+    fn cursor_ctx(&self) -> SavedCtx {
+        SavedCtx {
+            cursor_col: self.cursor.col.min(self.cols - 1),
+            cursor_row: self.cursor.row,
+            pen: self.pen,
+            origin_mode: self.origin_mode,
+            auto_wrap_mode: self.auto_wrap_mode,
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+        }
+    }
+
+    fn restore_ctx(&mut self, ctx: SavedCtx) {
+        self.cursor.col = ctx.cursor_col;
+        self.cursor.row = ctx.cursor_row;
+        self.pen = ctx.pen;
+        self.origin_mode = ctx.origin_mode;
+        self.auto_wrap_mode = ctx.auto_wrap_mode;
+        self.charsets = ctx.charsets;
+        self.active_charset = ctx.active_charset;
         self.next_print_wraps = false;
     }
 
@@ -464,16 +763,46 @@ impl Terminal {
 
     fn scroll_up_in_region(&mut self, n: usize) {
         let range = self.top_margin..self.bottom_margin + 1;
-        self.buffer.scroll_up(range.clone(), n, &self.pen);
+        self.buffer.scroll_up(range.clone(), n, &self.erase_pen());
+        self.scroll_sixels_up(range.clone(), n);
         self.dirty_lines.extend(range);
     }
 
     fn scroll_down_in_region(&mut self, n: usize) {
         let range = self.top_margin..self.bottom_margin + 1;
-        self.buffer.scroll_down(range.clone(), n, &self.pen);
+        self.buffer.scroll_down(range.clone(), n, &self.erase_pen());
+        self.scroll_sixels_down(range.clone(), n);
         self.dirty_lines.extend(range);
     }
 
+    // sixels
+
+    fn scroll_sixels_up(&mut self, range: Range<usize>, n: usize) {
+        self.sixels.retain_mut(|sixel| {
+            if !range.contains(&sixel.row) {
+                true
+            } else if sixel.row < range.start + n {
+                false
+            } else {
+                sixel.row -= n;
+                true
+            }
+        });
+    }
+
+    fn scroll_sixels_down(&mut self, range: Range<usize>, n: usize) {
+        self.sixels.retain_mut(|sixel| {
+            if !range.contains(&sixel.row) {
+                true
+            } else if sixel.row + n >= range.end {
+                false
+            } else {
+                sixel.row += n;
+                true
+            }
+        });
+    }
+
     // tabs
 
     fn set_tab(&mut self) {
@@ -495,9 +824,12 @@ impl Terminal {
     fn switch_to_alternate_buffer(&mut self) {
         if let BufferType::Primary = self.active_buffer_type {
             self.active_buffer_type = BufferType::Alternate;
+            self.buffer_transitions
+                .push(BufferTransition::EnteredAlternate);
             mem::swap(&mut self.saved_ctx, &mut self.alternate_saved_ctx);
             mem::swap(&mut self.buffer, &mut self.other_buffer);
-            self.buffer = Buffer::new(self.cols, self.rows, Some(0), Some(&self.pen));
+            self.buffer = Buffer::new(self.cols, self.rows, Some(0), Some(&self.erase_pen()));
+            self.sixels.clear();
             self.dirty_lines.extend(0..self.rows);
         }
     }
@@ -505,8 +837,11 @@ impl Terminal {
     fn switch_to_primary_buffer(&mut self) {
         if let BufferType::Alternate = self.active_buffer_type {
             self.active_buffer_type = BufferType::Primary;
+            self.buffer_transitions
+                .push(BufferTransition::LeftAlternate);
             mem::swap(&mut self.saved_ctx, &mut self.alternate_saved_ctx);
             mem::swap(&mut self.buffer, &mut self.other_buffer);
+            self.sixels.clear();
             self.dirty_lines.extend(0..self.rows);
         }
     }
@@ -524,6 +859,7 @@ impl Terminal {
 
         self.dirty_lines.resize(self.rows);
         self.dirty_lines.extend(0..self.rows);
+        self.sixels.clear();
 
         if self.saved_ctx.cursor_col >= self.cols {
             self.saved_ctx.cursor_col = self.cols - 1;
@@ -532,6 +868,14 @@ impl Terminal {
         if self.saved_ctx.cursor_row >= self.rows {
             self.saved_ctx.cursor_row = self.rows - 1;
         }
+
+        if self.sco_saved_ctx.cursor_col >= self.cols {
+            self.sco_saved_ctx.cursor_col = self.cols - 1;
+        }
+
+        if self.sco_saved_ctx.cursor_row >= self.rows {
+            self.sco_saved_ctx.cursor_row = self.rows - 1;
+        }
     }
 
     // resetting
@@ -546,12 +890,24 @@ impl Terminal {
         self.charsets = [Charset::Ascii, Charset::Ascii];
         self.active_charset = 0;
         self.saved_ctx = SavedCtx::default();
+        self.sco_saved_ctx = SavedCtx::default();
     }
 
     fn hard_reset(&mut self) {
-        let primary_buffer = Buffer::new(self.cols, self.rows, self.scrollback_limit, None);
+        let primary_buffer = primary_buffer(
+            self.cols,
+            self.rows,
+            self.scrollback_limit,
+            self.scrollback_slack,
+            self.scrollback_reserve,
+        );
         let alternate_buffer = Buffer::new(self.cols, self.rows, Some(0), None);
 
+        if self.is_alternate_buffer() {
+            self.buffer_transitions
+                .push(BufferTransition::LeftAlternate);
+        }
+
         self.buffer = primary_buffer;
         self.other_buffer = alternate_buffer;
         self.active_buffer_type = BufferType::Primary;
@@ -563,14 +919,28 @@ impl Terminal {
         self.insert_mode = false;
         self.origin_mode = false;
         self.auto_wrap_mode = true;
+        self.reverse_screen = false;
+        self.cursor_blink = false;
+        self.sixel_display_mode = false;
+        self.private_color_registers = true;
         self.new_line_mode = false;
+        self.keypad_app_mode = false;
+        self.send_8bit_controls = false;
+        self.bracketed_paste = false;
+        self.focus_reporting = false;
+        self.mouse_mode = MouseMode::Off;
+        self.mouse_encoding = MouseEncoding::Default;
         self.next_print_wraps = false;
         self.top_margin = 0;
         self.bottom_margin = self.rows - 1;
         self.saved_ctx = SavedCtx::default();
         self.alternate_saved_ctx = SavedCtx::default();
+        self.sco_saved_ctx = SavedCtx::default();
+        self.selectable = true;
         self.dirty_lines = DirtyLines::new(self.rows);
+        self.sixels.clear();
         self.resized = false;
+        self.generation = 0;
     }
 
     fn primary_buffer(&self) -> &Buffer {
@@ -581,6 +951,14 @@ impl Terminal {
         }
     }
 
+    fn primary_buffer_mut(&mut self) -> &mut Buffer {
+        if self.active_buffer_type == BufferType::Primary {
+            &mut self.buffer
+        } else {
+            &mut self.other_buffer
+        }
+    }
+
     fn alternate_buffer(&self) -> &Buffer {
         if self.active_buffer_type == BufferType::Alternate {
             &self.buffer
@@ -589,10 +967,23 @@ impl Terminal {
         }
     }
 
+    fn alternate_buffer_mut(&mut self) -> &mut Buffer {
+        if self.active_buffer_type == BufferType::Alternate {
+            &mut self.buffer
+        } else {
+            &mut self.other_buffer
+        }
+    }
+
     pub fn view(&self) -> &[Line] {
         self.buffer.view()
     }
 
+    #[cfg(feature = "binary")]
+    pub(crate) fn primary_view(&self) -> &[Line] {
+        self.primary_buffer().view()
+    }
+
     pub fn lines(&self) -> &[Line] {
         self.buffer.lines()
     }
@@ -605,15 +996,64 @@ impl Terminal {
         self.primary_buffer().text()
     }
 
+    pub fn text_with_wraps(&self) -> Vec<(String, bool)> {
+        self.primary_buffer().text_with_wraps()
+    }
+
+    pub fn sixels(&self) -> &[Sixel] {
+        &self.sixels
+    }
+
     pub fn cursor_keys_app_mode(&self) -> bool {
         self.cursor_keys_mode == CursorKeysMode::Application
     }
 
+    pub fn cursor_blink(&self) -> bool {
+        self.cursor_blink
+    }
+
+    pub fn sixel_display_mode(&self) -> bool {
+        self.sixel_display_mode
+    }
+
+    pub fn private_color_registers(&self) -> bool {
+        self.private_color_registers
+    }
+
+    pub fn reverse_screen_mode(&self) -> bool {
+        self.reverse_screen
+    }
+
+    pub fn origin_mode(&self) -> bool {
+        self.origin_mode
+    }
+
+    pub fn scroll_region(&self) -> (usize, usize) {
+        (self.top_margin, self.bottom_margin)
+    }
+
+    pub fn keypad_app_mode(&self) -> bool {
+        self.keypad_app_mode
+    }
+
+    pub fn input_modes(&self) -> InputModes {
+        InputModes {
+            cursor_keys_app_mode: self.cursor_keys_app_mode(),
+            keypad_app_mode: self.keypad_app_mode,
+            bracketed_paste: self.bracketed_paste,
+            focus_reporting: self.focus_reporting,
+            mouse_mode: self.mouse_mode,
+            mouse_encoding: self.mouse_encoding,
+            // tracked by the parser, filled in by Vt
+            vt52_mode: false,
+        }
+    }
+
     #[cfg(test)]
     pub fn verify(&self) {
         assert!(self.cursor.row < self.rows);
         assert!(self.lines().iter().all(|line| line.len() == self.cols));
-        assert!(!self.lines().last().unwrap().wrapped);
+        assert!(!self.lines().last().unwrap().wrapped());
 
         assert!(
             !self.next_print_wraps && self.cursor.col < self.cols
@@ -632,13 +1072,26 @@ impl Terminal {
         assert_eq!(self.insert_mode, other.insert_mode);
         assert_eq!(self.origin_mode, other.origin_mode);
         assert_eq!(self.auto_wrap_mode, other.auto_wrap_mode);
+        assert_eq!(self.reverse_screen, other.reverse_screen);
+        assert_eq!(self.title, other.title);
+        assert_eq!(self.cursor_blink, other.cursor_blink);
+        assert_eq!(self.sixel_display_mode, other.sixel_display_mode);
+        assert_eq!(self.private_color_registers, other.private_color_registers);
         assert_eq!(self.new_line_mode, other.new_line_mode);
         assert_eq!(self.cursor_keys_mode, other.cursor_keys_mode);
+        assert_eq!(self.keypad_app_mode, other.keypad_app_mode);
+        assert_eq!(self.send_8bit_controls, other.send_8bit_controls);
+        assert_eq!(self.bracketed_paste, other.bracketed_paste);
+        assert_eq!(self.focus_reporting, other.focus_reporting);
+        assert_eq!(self.mouse_mode, other.mouse_mode);
+        assert_eq!(self.mouse_encoding, other.mouse_encoding);
         assert_eq!(self.next_print_wraps, other.next_print_wraps);
         assert_eq!(self.top_margin, other.top_margin);
         assert_eq!(self.bottom_margin, other.bottom_margin);
         assert_eq!(self.saved_ctx, other.saved_ctx);
         assert_eq!(self.alternate_saved_ctx, other.alternate_saved_ctx);
+        assert_eq!(self.sco_saved_ctx, other.sco_saved_ctx);
+        assert_eq!(self.selectable, other.selectable);
         assert_eq!(self.primary_buffer().view(), other.primary_buffer().view());
 
         if self.active_buffer_type == BufferType::Alternate {
@@ -651,7 +1104,29 @@ impl Terminal {
 
     fn print(&mut self, mut ch: char) {
         ch = self.charsets[self.active_charset].translate(ch);
-        let cell = Cell::new(ch, self.pen);
+
+        // attach zero-width chars and ZWJ continuations to the previous cell
+        if self.cursor.col > 0 {
+            let prev = (self.cursor.col.min(self.cols) - 1, self.cursor.row);
+
+            if self.buffer[prev].combines_with(ch) {
+                self.buffer.combine(prev, ch, self.width_mode);
+                self.dirty_lines.add(self.cursor.row);
+
+                return;
+            }
+        } else if cell::is_zero_width(ch) {
+            // nothing to attach to, and it mustn't take a cell of its own
+            return;
+        }
+
+        let mut pen = self.pen;
+
+        if !self.selectable {
+            pen.set_unselectable();
+        }
+
+        let cell = Cell::with_width_mode(ch, pen, self.width_mode);
 
         if self.auto_wrap_mode && self.next_print_wraps {
             self.do_move_cursor_to_col(0);
@@ -688,6 +1163,16 @@ impl Terminal {
         self.dirty_lines.add(self.cursor.row);
     }
 
+    fn apc(&mut self, data: String) {
+        if let Some(strings) = &mut self.apc_strings {
+            strings.push(data);
+        }
+    }
+
+    fn bel(&mut self) {
+        self.bell = true;
+    }
+
     fn bs(&mut self) {
         if self.next_print_wraps {
             self.move_cursor_to_rel_col(-2);
@@ -700,6 +1185,19 @@ impl Terminal {
         self.move_cursor_to_next_tab(1);
     }
 
+    fn enq(&mut self) {
+        self.response.push_str(&self.answerback);
+    }
+
+    fn ff(&mut self) {
+        if self.form_feed_clears {
+            self.ed(EdScope::All);
+            self.move_cursor_home();
+        } else {
+            self.lf();
+        }
+    }
+
     fn lf(&mut self) {
         self.move_cursor_down_with_scroll();
 
@@ -755,8 +1253,30 @@ impl Terminal {
                 self.buffer.print((col, row), '\u{45}'.into());
             }
 
+            self.buffer[row].set_wrapped(false);
             self.dirty_lines.add(row);
         }
+
+        self.top_margin = 0;
+        self.bottom_margin = self.rows - 1;
+        self.do_move_cursor_to_col(0);
+        self.do_move_cursor_to_row(0);
+    }
+
+    fn deckpam(&mut self) {
+        self.keypad_app_mode = true;
+    }
+
+    fn deckpnm(&mut self) {
+        self.keypad_app_mode = false;
+    }
+
+    fn s7c1t(&mut self) {
+        self.send_8bit_controls = false;
+    }
+
+    fn s8c1t(&mut self) {
+        self.send_8bit_controls = true;
     }
 
     fn gzd4(&mut self, charset: Charset) {
@@ -768,10 +1288,14 @@ impl Terminal {
     }
 
     fn ich(&mut self, n: u16) {
+        if self.cursor.col >= self.cols {
+            self.move_cursor_to_col(self.cols - 1);
+        }
+
         self.buffer.insert(
             (self.cursor.col, self.cursor.row),
             as_usize(n, 1),
-            Cell::blank(self.pen),
+            Cell::blank(self.erase_pen()),
         );
 
         self.dirty_lines.add(self.cursor.row);
@@ -822,13 +1346,27 @@ impl Terminal {
         self.move_cursor_to_next_tab(as_usize(n, 1));
     }
 
+    fn erase_position(&self) -> (usize, usize) {
+        (self.cursor.col.min(self.cols - 1), self.cursor.row)
+    }
+
+    // pen used for cells blanked by erase, insert/delete and scroll ops,
+    // carrying the current colors when background color erase is on
+    fn erase_pen(&self) -> Pen {
+        if self.bce {
+            self.pen
+        } else {
+            Pen::default()
+        }
+    }
+
     fn ed(&mut self, scope: EdScope) {
         match scope {
             EdScope::Below => {
                 self.buffer.erase(
-                    (self.cursor.col, self.cursor.row),
+                    self.erase_position(),
                     EraseMode::FromCursorToEndOfView,
-                    &self.pen,
+                    &self.erase_pen(),
                 );
 
                 self.dirty_lines.extend(self.cursor.row..self.rows);
@@ -836,9 +1374,9 @@ impl Terminal {
 
             EdScope::Above => {
                 self.buffer.erase(
-                    (self.cursor.col, self.cursor.row),
+                    self.erase_position(),
                     EraseMode::FromStartOfViewToCursor,
-                    &self.pen,
+                    &self.erase_pen(),
                 );
 
                 self.dirty_lines.extend(0..self.cursor.row + 1);
@@ -846,15 +1384,20 @@ impl Terminal {
 
             EdScope::All => {
                 self.buffer.erase(
-                    (self.cursor.col, self.cursor.row),
+                    self.erase_position(),
                     EraseMode::WholeView,
-                    &self.pen,
+                    &self.erase_pen(),
                 );
 
+                self.sixels.clear();
+
                 self.dirty_lines.extend(0..self.rows);
             }
 
-            _ => {}
+            EdScope::SavedLines => {
+                let lines: Vec<Line> = self.primary_buffer_mut().clear_scrollback().collect();
+                self.cleared_scrollback.extend(lines);
+            }
         }
     }
 
@@ -862,9 +1405,9 @@ impl Terminal {
         match scope {
             ElScope::ToRight => {
                 self.buffer.erase(
-                    (self.cursor.col, self.cursor.row),
+                    self.erase_position(),
                     EraseMode::FromCursorToEndOfLine,
-                    &self.pen,
+                    &self.erase_pen(),
                 );
 
                 self.dirty_lines.add(self.cursor.row);
@@ -872,9 +1415,9 @@ impl Terminal {
 
             ElScope::ToLeft => {
                 self.buffer.erase(
-                    (self.cursor.col, self.cursor.row),
+                    self.erase_position(),
                     EraseMode::FromStartOfLineToCursor,
-                    &self.pen,
+                    &self.erase_pen(),
                 );
 
                 self.dirty_lines.add(self.cursor.row);
@@ -882,9 +1425,9 @@ impl Terminal {
 
             ElScope::All => {
                 self.buffer.erase(
-                    (self.cursor.col, self.cursor.row),
+                    self.erase_position(),
                     EraseMode::WholeLine,
-                    &self.pen,
+                    &self.erase_pen(),
                 );
 
                 self.dirty_lines.add(self.cursor.row);
@@ -893,31 +1436,35 @@ impl Terminal {
     }
 
     fn il(&mut self, n: u16) {
-        let range = if self.cursor.row <= self.bottom_margin {
-            self.cursor.row..self.bottom_margin + 1
-        } else {
-            self.cursor.row..self.rows
-        };
+        if !self.cursor_in_scroll_region() {
+            return;
+        }
 
-        self.buffer
-            .scroll_down(range.clone(), as_usize(n, 1), &self.pen);
+        let range = self.cursor.row..self.bottom_margin + 1;
 
+        let n = as_usize(n, 1);
+        self.buffer.scroll_down(range.clone(), n, &self.erase_pen());
+        self.scroll_sixels_down(range.clone(), n);
         self.dirty_lines.extend(range);
     }
 
     fn dl(&mut self, n: u16) {
-        let range = if self.cursor.row <= self.bottom_margin {
-            self.cursor.row..self.bottom_margin + 1
-        } else {
-            self.cursor.row..self.rows
-        };
+        if !self.cursor_in_scroll_region() {
+            return;
+        }
 
-        self.buffer
-            .scroll_up(range.clone(), as_usize(n, 1), &self.pen);
+        let range = self.cursor.row..self.bottom_margin + 1;
 
+        let n = as_usize(n, 1);
+        self.buffer.scroll_up(range.clone(), n, &self.erase_pen());
+        self.scroll_sixels_up(range.clone(), n);
         self.dirty_lines.extend(range);
     }
 
+    fn cursor_in_scroll_region(&self) -> bool {
+        self.cursor.row >= self.top_margin && self.cursor.row <= self.bottom_margin
+    }
+
     fn dch(&mut self, n: u16) {
         if self.cursor.col >= self.cols {
             self.move_cursor_to_col(self.cols - 1);
@@ -926,12 +1473,59 @@ impl Terminal {
         self.buffer.delete(
             (self.cursor.col, self.cursor.row),
             as_usize(n, 1),
-            &self.pen,
+            &self.erase_pen(),
         );
 
         self.dirty_lines.add(self.cursor.row);
     }
 
+    fn dsr(&mut self, op: DsrOp) {
+        match op {
+            DsrOp::Status => {
+                self.respond(&format!("{}0n", self.csi()));
+            }
+
+            DsrOp::CursorPosition => {
+                let col = self.cursor.col.min(self.cols - 1);
+                let row = self.cursor.row - self.actual_top_margin().min(self.cursor.row);
+
+                self.respond(&format!("{}{};{}R", self.csi(), row + 1, col + 1));
+            }
+        }
+    }
+
+    fn osc(&mut self, op: OscOp) {
+        match op {
+            OscOp::SetTitle(title) => {
+                self.title = title;
+                self.title_changed = true;
+            }
+
+            OscOp::PromptStart => {
+                self.selectable = false;
+            }
+
+            OscOp::PromptEnd => {
+                self.selectable = true;
+            }
+        }
+    }
+
+    fn sixel(&mut self, data: String) {
+        let row = self.cursor.row;
+        let col = self.cursor.col.min(self.cols - 1);
+        self.sixels.push(Sixel { row, col, data });
+        self.dirty_lines.add(row);
+    }
+
+    fn scosc(&mut self) {
+        self.sco_saved_ctx = self.cursor_ctx();
+    }
+
+    fn scorc(&mut self) {
+        self.restore_ctx(self.sco_saved_ctx.clone());
+    }
+
     fn su(&mut self, n: u16) {
         self.scroll_up_in_region(as_usize(n, 1));
     }
@@ -960,9 +1554,9 @@ impl Terminal {
         let n = as_usize(n, 1);
 
         self.buffer.erase(
-            (self.cursor.col, self.cursor.row),
+            self.erase_position(),
             EraseMode::NextChars(n),
-            &self.pen,
+            &self.erase_pen(),
         );
 
         self.dirty_lines.add(self.cursor.row);
@@ -991,6 +1585,10 @@ impl Terminal {
         self.cursor_down(as_usize(n, 1));
     }
 
+    fn vt52_identify(&mut self) {
+        self.response.push_str("\x1b/Z");
+    }
+
     fn tbc(&mut self, scope: TbcScope) {
         match scope {
             TbcScope::CurrentColumn => {
@@ -1072,6 +1670,10 @@ impl Terminal {
                     self.pen.set_strikethrough();
                 }
 
+                SetDoubleUnderline => {
+                    self.pen.set_double_underline();
+                }
+
                 ResetIntensity => {
                     self.pen.intensity = Intensity::Normal;
                 }
@@ -1128,8 +1730,45 @@ impl Terminal {
     }
 
     fn xtwinops(&mut self, op: XtwinopsOp) {
+        use XtwinopsOp::*;
+
+        match op {
+            Resize(cols, rows) => {
+                self.resize_window(cols, rows);
+            }
+
+            ReportWindowState => {
+                // always report non-iconified window
+                self.respond(&format!("{}1t", self.csi()));
+            }
+
+            ReportWindowPosition => {
+                self.respond(&format!("{}3;0;0t", self.csi()));
+            }
+
+            ReportPixelSize => {
+                let (width, height) = self.cell_size;
+
+                self.respond(&format!(
+                    "{}4;{};{}t",
+                    self.csi(),
+                    self.rows * height,
+                    self.cols * width
+                ));
+            }
+
+            ReportTextAreaSize => {
+                self.respond(&format!("{}8;{};{}t", self.csi(), self.rows, self.cols));
+            }
+
+            ReportScreenSize => {
+                self.respond(&format!("{}9;{};{}t", self.csi(), self.rows, self.cols));
+            }
+        }
+    }
+
+    fn resize_window(&mut self, cols: u16, rows: u16) {
         if self.resizable {
-            let XtwinopsOp::Resize(cols, rows) = op;
             let cols = as_usize(cols, self.cols);
             let rows = as_usize(rows, self.rows);
 
@@ -1173,15 +1812,45 @@ impl Terminal {
         self.soft_reset();
     }
 
+    fn decrqss(&mut self, setting: String) {
+        let value = match setting.as_str() {
+            "m" => Some(format!("{}m", self.pen.sgr_params())),
+            "r" => Some(format!(
+                "{};{}r",
+                self.top_margin + 1,
+                self.bottom_margin + 1
+            )),
+            _ => None,
+        };
+
+        match value {
+            Some(value) => {
+                self.respond(&format!("{}1$r{value}{}", self.dcs(), self.st()));
+            }
+
+            None => {
+                self.respond(&format!("{}0$r{}", self.dcs(), self.st()));
+            }
+        }
+    }
+
     fn decset(&mut self, modes: Vec<DecMode>) {
         use DecMode::*;
 
         for mode in modes {
             match mode {
+                // VT52 mode is entered and left in the parser
+                Ansi => {}
+
                 CursorKeys => {
                     self.cursor_keys_mode = CursorKeysMode::Application;
                 }
 
+                ReverseScreen => {
+                    self.reverse_screen = true;
+                    self.dirty_lines.extend(0..self.rows);
+                }
+
                 Origin => {
                     self.origin_mode = true;
                     self.move_cursor_home();
@@ -1191,10 +1860,59 @@ impl Terminal {
                     self.auto_wrap_mode = true;
                 }
 
+                CursorBlink => {
+                    self.cursor_blink = true;
+                }
+
                 TextCursorEnable => {
                     self.cursor.visible = true;
                 }
 
+                // sixel modes are only tracked, sixel rendering doesn't use them yet
+                SixelDisplay => {
+                    self.sixel_display_mode = true;
+                }
+
+                PrivateColorRegisters => {
+                    self.private_color_registers = true;
+                }
+
+                X10Mouse => {
+                    self.mouse_mode = MouseMode::X10;
+                }
+
+                NormalMouse => {
+                    self.mouse_mode = MouseMode::Normal;
+                }
+
+                ButtonEventMouse => {
+                    self.mouse_mode = MouseMode::ButtonEvent;
+                }
+
+                AnyEventMouse => {
+                    self.mouse_mode = MouseMode::AnyEvent;
+                }
+
+                FocusEvent => {
+                    self.focus_reporting = true;
+                }
+
+                Utf8Mouse => {
+                    self.mouse_encoding = MouseEncoding::Utf8;
+                }
+
+                SgrMouse => {
+                    self.mouse_encoding = MouseEncoding::Sgr;
+                }
+
+                UrxvtMouse => {
+                    self.mouse_encoding = MouseEncoding::Urxvt;
+                }
+
+                BracketedPaste => {
+                    self.bracketed_paste = true;
+                }
+
                 AltScreenBuffer => {
                     self.switch_to_alternate_buffer();
                     self.reflow();
@@ -1218,10 +1936,17 @@ impl Terminal {
 
         for mode in modes {
             match mode {
+                Ansi => {}
+
                 CursorKeys => {
                     self.cursor_keys_mode = CursorKeysMode::Normal;
                 }
 
+                ReverseScreen => {
+                    self.reverse_screen = false;
+                    self.dirty_lines.extend(0..self.rows);
+                }
+
                 Origin => {
                     self.origin_mode = false;
                     self.move_cursor_home();
@@ -1231,10 +1956,48 @@ impl Terminal {
                     self.auto_wrap_mode = false;
                 }
 
+                CursorBlink => {
+                    self.cursor_blink = false;
+                }
+
                 TextCursorEnable => {
                     self.cursor.visible = false;
                 }
 
+                SixelDisplay => {
+                    self.sixel_display_mode = false;
+                }
+
+                PrivateColorRegisters => {
+                    self.private_color_registers = false;
+                }
+
+                // like xterm, resetting any of the tracking modes turns
+                // tracking off
+                X10Mouse | NormalMouse | ButtonEventMouse | AnyEventMouse => {
+                    self.mouse_mode = MouseMode::Off;
+                }
+
+                FocusEvent => {
+                    self.focus_reporting = false;
+                }
+
+                Utf8Mouse => {
+                    self.reset_mouse_encoding(MouseEncoding::Utf8);
+                }
+
+                SgrMouse => {
+                    self.reset_mouse_encoding(MouseEncoding::Sgr);
+                }
+
+                UrxvtMouse => {
+                    self.reset_mouse_encoding(MouseEncoding::Urxvt);
+                }
+
+                BracketedPaste => {
+                    self.bracketed_paste = false;
+                }
+
                 AltScreenBuffer => {
                     self.switch_to_primary_buffer();
                     self.reflow();
@@ -1253,7 +2016,39 @@ impl Terminal {
         }
     }
 
+    fn reset_mouse_encoding(&mut self, encoding: MouseEncoding) {
+        if self.mouse_encoding == encoding {
+            self.mouse_encoding = MouseEncoding::Default;
+        }
+    }
+
     pub fn dump(&self) -> String {
+        self.dump_with(true)
+    }
+
+    // same as dump() but without screen buffer contents, which are then
+    // restored separately with restore_views()
+    #[cfg(feature = "binary")]
+    pub(crate) fn dump_state(&self) -> String {
+        self.dump_with(false)
+    }
+
+    #[cfg(feature = "binary")]
+    pub(crate) fn restore_views(&mut self, primary: Vec<Line>, alternate: Option<Vec<Line>>) {
+        for (row, line) in primary.into_iter().enumerate() {
+            self.primary_buffer_mut()[row] = line;
+        }
+
+        if let Some(alternate) = alternate.filter(|_| self.is_alternate_buffer()) {
+            for (row, line) in alternate.into_iter().enumerate() {
+                self.buffer[row] = line;
+            }
+        }
+
+        self.dirty_lines.extend(0..self.rows);
+    }
+
+    fn dump_with(&self, buffers: bool) -> String {
         let (primary_ctx, alternate_ctx): (&SavedCtx, &SavedCtx) = match self.active_buffer_type {
             BufferType::Primary => (&self.saved_ctx, &self.alternate_saved_ctx),
             BufferType::Alternate => (&self.alternate_saved_ctx, &self.saved_ctx),
@@ -1261,17 +2056,24 @@ impl Terminal {
 
         // 1. dump primary screen buffer
 
-        // TODO don't include trailing empty lines
-        let mut seq: String = self.primary_buffer().dump();
+        let mut seq = String::new();
+
+        if buffers {
+            // TODO don't include trailing empty lines
+            seq.push_str(&self.primary_buffer().dump());
+        }
 
         // 2. setup tab stops
 
-        // clear all tab stops
-        seq.push_str("\u{9b}5W");
+        // default tab stops are already in place in a fresh terminal
+        if self.tabs.is_custom() {
+            // clear all tab stops
+            seq.push_str("\u{9b}5W");
 
-        // set each tab stop
-        for t in &self.tabs {
-            seq.push_str(&format!("\u{9b}{}`\u{1b}[W", t + 1));
+            // set each tab stop
+            for t in &self.tabs {
+                seq.push_str(&format!("\u{9b}{}`\u{1b}[W", t + 1));
+            }
         }
 
         // 3. configure saved context for primary screen
@@ -1296,9 +2098,15 @@ impl Terminal {
         // configure pen
         seq.push_str(&primary_ctx.pen.dump());
 
+        // configure charsets
+        seq.push_str(&dump_charsets(primary_ctx));
+
         // save cursor
         seq.push_str("\u{1b}7");
 
+        // reset charsets so that following prints aren't translated
+        seq.push_str(&reset_charsets(primary_ctx));
+
         if !primary_ctx.auto_wrap_mode {
             // re-enable auto-wrap mode
             seq.push_str("\u{9b}?7h");
@@ -1309,12 +2117,53 @@ impl Terminal {
             seq.push_str("\u{9b}?6l");
         }
 
-        // 4. dump alternate screen buffer
+        // 4. configure ANSI.SYS saved context
+
+        if !self.sco_saved_ctx.auto_wrap_mode {
+            // disable auto-wrap mode
+            seq.push_str("\u{9b}?7l");
+        }
+
+        if self.sco_saved_ctx.origin_mode {
+            // enable origin mode
+            seq.push_str("\u{9b}?6h");
+        }
+
+        // fix cursor in target position
+        seq.push_str(&format!(
+            "\u{9b}{};{}H",
+            self.sco_saved_ctx.cursor_row + 1,
+            self.sco_saved_ctx.cursor_col + 1
+        ));
+
+        // configure pen
+        seq.push_str(&self.sco_saved_ctx.pen.dump());
+
+        // configure charsets
+        seq.push_str(&dump_charsets(&self.sco_saved_ctx));
+
+        // save cursor (ANSI.SYS)
+        seq.push_str("\u{9b}s");
+
+        // reset charsets
+        seq.push_str(&reset_charsets(&self.sco_saved_ctx));
+
+        if !self.sco_saved_ctx.auto_wrap_mode {
+            // re-enable auto-wrap mode
+            seq.push_str("\u{9b}?7h");
+        }
+
+        if self.sco_saved_ctx.origin_mode {
+            // re-disable origin mode
+            seq.push_str("\u{9b}?6l");
+        }
+
+        // 5. dump alternate screen buffer
 
         // switch to alternate screen
         seq.push_str("\u{9b}?1047h");
 
-        if self.active_buffer_type == BufferType::Alternate {
+        if buffers && self.active_buffer_type == BufferType::Alternate {
             // move cursor home
             seq.push_str("\u{9b}1;1H");
 
@@ -1322,7 +2171,7 @@ impl Terminal {
             seq.push_str(&self.alternate_buffer().dump());
         }
 
-        // 5. configure saved context for alternate screen
+        // 6. configure saved context for alternate screen
 
         if !alternate_ctx.auto_wrap_mode {
             // disable auto-wrap mode
@@ -1344,9 +2193,15 @@ impl Terminal {
         // configure pen
         seq.push_str(&alternate_ctx.pen.dump());
 
+        // configure charsets
+        seq.push_str(&dump_charsets(alternate_ctx));
+
         // save cursor
         seq.push_str("\u{1b}7");
 
+        // reset charsets
+        seq.push_str(&reset_charsets(alternate_ctx));
+
         if !alternate_ctx.auto_wrap_mode {
             // re-enable auto-wrap mode
             seq.push_str("\u{9b}?7h");
@@ -1357,14 +2212,14 @@ impl Terminal {
             seq.push_str("\u{9b}?6l");
         }
 
-        // 6. ensure the right buffer is active
+        // 7. ensure the right buffer is active
 
         if self.active_buffer_type == BufferType::Primary {
             // switch back to primary screen
             seq.push_str("\u{9b}?1047l");
         }
 
-        // 7. setup origin mode
+        // 8. setup origin mode
 
         if self.origin_mode {
             // enable origin mode
@@ -1372,7 +2227,7 @@ impl Terminal {
             seq.push_str("\u{9b}?6h");
         }
 
-        // 8. setup margins
+        // 9. setup margins
 
         // note: this resets cursor position - must be done before fixing cursor
         seq.push_str(&format!(
@@ -1381,40 +2236,87 @@ impl Terminal {
             self.bottom_margin + 1
         ));
 
-        // 9. setup cursor
+        // 10. setup cursor
 
         let col = self.cursor.col;
         let mut row = self.cursor.row;
 
         if self.origin_mode {
             if row < self.top_margin || row > self.bottom_margin {
-                // bring cursor outside scroll region by restoring saved cursor
-                // and moving it to desired position via CSI A/B/C/D
+                // bring cursor outside scroll region by restoring a saved
+                // context and moving it to desired position via CSI A/B/C/D -
+                // restoring is the only way to enable origin mode without
+                // homing the cursor, and relative moves can't cross the
+                // region, so prefer a context with origin mode on the same
+                // side of it
+
+                let above = row < self.top_margin;
+
+                let fits = |ctx: &SavedCtx| {
+                    ctx.origin_mode
+                        && if above {
+                            ctx.cursor_row < self.top_margin
+                        } else {
+                            ctx.cursor_row > self.bottom_margin
+                        }
+                };
+
+                let (ctx, restore) = if fits(&self.saved_ctx) {
+                    (&self.saved_ctx, "\u{1b}8")
+                } else if fits(&self.sco_saved_ctx) {
+                    (&self.sco_saved_ctx, "\u{9b}u")
+                } else if self.active_buffer_type == BufferType::Primary
+                    && fits(&self.alternate_saved_ctx)
+                {
+                    // borrow the alternate screen's saved context
+                    (&self.alternate_saved_ctx, "\u{9b}?1047h\u{1b}8\u{9b}?1047l")
+                } else {
+                    (&self.saved_ctx, "\u{1b}8")
+                };
+
+                seq.push_str(restore);
+
+                if !ctx.auto_wrap_mode {
+                    // re-enable auto-wrap mode
+                    seq.push_str("\u{9b}?7h");
+                }
+
+                if !ctx.origin_mode {
+                    // best effort: enable origin mode (homes the cursor)
+                    seq.push_str("\u{9b}?6h");
+                }
+
+                // reset charsets (step 11 sets the right ones)
+                seq.push_str(&reset_charsets(ctx));
 
-                seq.push_str("\u{9b}u");
+                let (ctx_col, ctx_row) = if ctx.origin_mode {
+                    (ctx.cursor_col, ctx.cursor_row)
+                } else {
+                    (0, self.top_margin)
+                };
 
-                match col.cmp(&self.saved_ctx.cursor_col) {
+                match col.cmp(&ctx_col) {
                     Ordering::Less => {
-                        let n = self.saved_ctx.cursor_col - col;
+                        let n = ctx_col - col;
                         seq.push_str(&format!("\u{9b}{n}D"));
                     }
 
                     Ordering::Greater => {
-                        let n = col - self.saved_ctx.cursor_col;
+                        let n = col - ctx_col;
                         seq.push_str(&format!("\u{9b}{n}C"));
                     }
 
                     Ordering::Equal => (),
                 }
 
-                match row.cmp(&self.saved_ctx.cursor_row) {
+                match row.cmp(&ctx_row) {
                     Ordering::Less => {
-                        let n = self.saved_ctx.cursor_row - row;
+                        let n = ctx_row - row;
                         seq.push_str(&format!("\u{9b}{n}A"));
                     }
 
                     Ordering::Greater => {
-                        let n = row - self.saved_ctx.cursor_row;
+                        let n = row - ctx_row;
                         seq.push_str(&format!("\u{9b}{n}B"));
                     }
 
@@ -1431,8 +2333,8 @@ impl Terminal {
         if self.cursor.col >= self.cols {
             // move cursor past right border by re-printing the character in
             // the last column
-            let cell = self.buffer[(self.cols - 1, self.cursor.row)];
-            seq.push_str(&format!("{}{}", cell.pen().dump(), cell.char()));
+            let cell = &self.buffer[(self.cols - 1, self.cursor.row)];
+            seq.push_str(&cell.dump());
         }
 
         // configure pen
@@ -1446,7 +2348,7 @@ impl Terminal {
         // Following 3 steps must happen after ALL prints as they alter print behaviour,
         // including the "move cursor past right border one" above.
 
-        // 10. setup charset
+        // 11. setup charset
 
         if self.charsets[0] == Charset::Drawing {
             // put drawing charset into G0 slot
@@ -1463,38 +2365,176 @@ impl Terminal {
             seq.push('\u{0e}');
         }
 
-        // 11. setup insert mode
+        // 12. setup insert mode
 
         if self.insert_mode {
             // enable insert mode
             seq.push_str("\u{9b}4h");
         }
 
-        // 12. setup auto-wrap mode
+        // 13. setup auto-wrap mode
 
         if !self.auto_wrap_mode {
             // disable auto-wrap mode
             seq.push_str("\u{9b}?7l");
         }
 
-        // 13. setup new line mode
+        // 14. setup new line mode
 
         if self.new_line_mode {
             // enable new line mode
             seq.push_str("\u{9b}20h");
         }
 
-        // 14. setup cursor key mode
+        // 15. setup cursor key mode
 
         if self.cursor_keys_mode == CursorKeysMode::Application {
             // enable new line mode
             seq.push_str("\u{9b}?1h");
         }
 
+        // 16. setup keypad mode
+
+        if self.keypad_app_mode {
+            // enable keypad application mode
+            seq.push_str("\u{1b}=");
+        }
+
+        // 17. setup C1 control transmission
+
+        if self.send_8bit_controls {
+            // enable 8-bit C1 controls in responses
+            seq.push_str("\u{1b} G");
+        }
+
+        // 18. setup mouse and other input modes
+
+        match self.mouse_mode {
+            MouseMode::Off => {}
+            MouseMode::X10 => seq.push_str("\u{9b}?9h"),
+            MouseMode::Normal => seq.push_str("\u{9b}?1000h"),
+            MouseMode::ButtonEvent => seq.push_str("\u{9b}?1002h"),
+            MouseMode::AnyEvent => seq.push_str("\u{9b}?1003h"),
+        }
+
+        match self.mouse_encoding {
+            MouseEncoding::Default => {}
+            MouseEncoding::Utf8 => seq.push_str("\u{9b}?1005h"),
+            MouseEncoding::Sgr => seq.push_str("\u{9b}?1006h"),
+            MouseEncoding::Urxvt => seq.push_str("\u{9b}?1015h"),
+        }
+
+        if self.focus_reporting {
+            // enable focus reporting
+            seq.push_str("\u{9b}?1004h");
+        }
+
+        if self.bracketed_paste {
+            // enable bracketed paste mode
+            seq.push_str("\u{9b}?2004h");
+        }
+
+        // 19. setup reverse screen mode
+
+        if self.reverse_screen {
+            // enable reverse video for the whole screen
+            seq.push_str("\u{9b}?5h");
+        }
+
+        // 20. setup cursor blinking
+
+        if self.cursor_blink {
+            // enable cursor blinking
+            seq.push_str("\u{9b}?12h");
+        }
+
+        // 21. setup sixel modes
+
+        if self.sixel_display_mode {
+            // enable sixel display mode
+            seq.push_str("\u{9b}?80h");
+        }
+
+        if !self.private_color_registers {
+            // disable private color registers
+            seq.push_str("\u{9b}?1070l");
+        }
+
+        // 22. setup title
+
+        if !self.title.is_empty() {
+            seq.push_str(&format!("\u{9d}2;{}\u{9c}", self.title));
+        }
+
+        // 23. setup prompt zone
+
+        if !self.selectable {
+            seq.push_str(PROMPT_START);
+        }
+
         seq
     }
 }
 
+fn primary_buffer(
+    cols: usize,
+    rows: usize,
+    scrollback_limit: Option<usize>,
+    scrollback_slack: bool,
+    scrollback_reserve: usize,
+) -> Buffer {
+    let mut buffer = Buffer::new(cols, rows, scrollback_limit, None);
+
+    if scrollback_limit.is_none() {
+        buffer.reserve_scrollback(scrollback_reserve);
+    }
+
+    if !scrollback_slack {
+        buffer.disable_scrollback_slack();
+    }
+
+    buffer
+}
+
+// designates G0/G1 and shifts as in the saved context, assuming both slots
+// currently hold ASCII and G0 is active
+fn dump_charsets(ctx: &SavedCtx) -> String {
+    let mut seq = String::new();
+
+    if ctx.charsets[0] == Charset::Drawing {
+        seq.push_str("\u{1b}(0");
+    }
+
+    if ctx.charsets[1] == Charset::Drawing {
+        seq.push_str("\u{1b})0");
+    }
+
+    if ctx.active_charset == 1 {
+        seq.push('\u{0e}');
+    }
+
+    seq
+}
+
+// undoes dump_charsets
+fn reset_charsets(ctx: &SavedCtx) -> String {
+    let mut seq = String::new();
+
+    if ctx.charsets[0] == Charset::Drawing {
+        seq.push_str("\u{1b}(B");
+    }
+
+    if ctx.charsets[1] == Charset::Drawing {
+        seq.push_str("\u{1b})B");
+    }
+
+    if ctx.active_charset == 1 {
+        seq.push('\u{0f}');
+    }
+
+    seq
+}
+
 fn as_usize(value: u16, default: usize) -> usize {
     if value == 0 {
         default
@@ -1513,7 +2553,7 @@ impl Default for Terminal {
 mod tests {
     use super::Terminal;
     use crate::color::Color;
-    use crate::parser::{DecMode, Function, SgrOp, XtwinopsOp};
+    use crate::parser::{DecMode, Function, SgrOp, TbcScope, XtwinopsOp};
     use crate::pen::Intensity;
     use Function::*;
     use SgrOp::*;
@@ -1542,6 +2582,24 @@ mod tests {
 
         assert!(term.pen.is_underline());
 
+        term.execute(sgr(SetDoubleUnderline));
+
+        assert!(term.pen.is_double_underline());
+        assert!(!term.pen.is_underline());
+
+        term.execute(sgr(ResetIntensity));
+
+        assert!(term.pen.is_double_underline());
+        assert_eq!(term.pen.intensity, Intensity::Normal);
+
+        term.execute(sgr(SetFaintIntensity));
+        term.execute(sgr(ResetUnderline));
+
+        assert!(!term.pen.is_double_underline());
+        assert_eq!(term.pen.intensity, Intensity::Faint);
+
+        term.execute(sgr(SetUnderline));
+
         term.execute(sgr(SetBlink));
 
         assert!(term.pen.is_blink());
@@ -1603,6 +2661,20 @@ mod tests {
         term.execute(Xtwinops(Resize(20, 0)));
 
         assert_eq!(term.tabs, vec![8, 16]);
+
+        // custom tab stops
+
+        term.execute(Tbc(TbcScope::All));
+        term.execute(Cup(1, 4));
+        term.execute(Hts);
+        term.execute(Xtwinops(Resize(40, 0)));
+
+        assert_eq!(term.tabs, vec![3]);
+
+        term.execute(Ris);
+        term.execute(Xtwinops(Resize(50, 0)));
+
+        assert_eq!(term.tabs, vec![8, 16, 24, 32, 40, 48]);
     }
 
     #[test]
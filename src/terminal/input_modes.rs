@@ -0,0 +1,27 @@
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InputModes {
+    pub cursor_keys_app_mode: bool,
+    pub keypad_app_mode: bool,
+    pub bracketed_paste: bool,
+    pub focus_reporting: bool,
+    pub mouse_mode: MouseMode,
+    pub mouse_encoding: MouseEncoding,
+    pub vt52_mode: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MouseMode {
+    Off,
+    X10,
+    Normal,
+    ButtonEvent,
+    AnyEvent,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MouseEncoding {
+    Default,
+    Utf8,
+    Sgr,
+    Urxvt,
+}
@@ -0,0 +1,6 @@
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sixel {
+    pub row: usize,
+    pub col: usize,
+    pub data: String,
+}
@@ -13,7 +13,7 @@ impl TextUnwrapper {
     }
 
     pub fn push(&mut self, line: &Line) -> Option<String> {
-        if line.wrapped {
+        if line.wrapped() {
             self.wrapped_line.push_str(&line.text());
 
             None
@@ -93,7 +93,7 @@ mod tests {
         let mut line = Line::blank(5, pen);
         line.print(0, 'a'.into());
         line.print(4, 'b'.into());
-        line.wrapped = false;
+        line.set_wrapped(false);
 
         let text = tu.push(&line);
 
@@ -102,7 +102,7 @@ mod tests {
         let mut line = Line::blank(5, pen);
         line.print(0, 'c'.into());
         line.print(4, 'd'.into());
-        line.wrapped = true;
+        line.set_wrapped(true);
 
         let text = tu.push(&line);
 
@@ -111,7 +111,7 @@ mod tests {
         let mut line = Line::blank(5, pen);
         line.print(0, 'e'.into());
         line.print(4, 'f'.into());
-        line.wrapped = true;
+        line.set_wrapped(true);
 
         let text = tu.push(&line);
 
@@ -120,7 +120,7 @@ mod tests {
         let mut line = Line::blank(5, pen);
         line.print(0, 'g'.into());
         line.print(1, 'h'.into());
-        line.wrapped = false;
+        line.set_wrapped(false);
 
         let text = tu.push(&line);
 
@@ -128,7 +128,7 @@ mod tests {
 
         let mut line = Line::blank(5, pen);
         line.print(0, 'i'.into());
-        line.wrapped = true;
+        line.set_wrapped(true);
 
         let text = tu.push(&line);
 
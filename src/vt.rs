@@ -1,6 +1,18 @@
+#[cfg(feature = "binary")]
+use crate::binary::{Decoder, Encoder};
+use crate::cell::{Cell, WidthMode};
+use crate::color::Color;
+#[cfg(feature = "html")]
+use crate::html::{self, HtmlOptions};
+use crate::input::{self, Key, Modifiers};
 use crate::line::Line;
-use crate::parser::Parser;
-use crate::terminal::{Cursor, Terminal};
+use crate::parser::{DecMode, Function, Parser, State, DEFAULT_MAX_STRING_LEN};
+use crate::pen::Intensity;
+use crate::terminal::{BufferTransition, Cursor, InputModes, Sixel, Terminal};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::ops::Range;
 
 #[derive(Debug)]
 pub struct Vt {
@@ -22,22 +34,197 @@ impl Vt {
             .filter_map(|ch| self.parser.feed(ch))
             .for_each(|op| self.terminal.execute(op));
 
+        self.changes()
+    }
+
+    pub fn feed_str_changed(&mut self, s: &str) -> bool {
+        let before: Vec<u64> = self.view().iter().map(line_hash).collect();
+        s.chars().for_each(|ch| self.feed(ch));
+
+        // only line changes are consumed here, the response, buffer
+        // transitions and scrollback stay pending for the next feed_str
+        let (lines, resized) = self.terminal.changes();
+
+        if resized {
+            return true;
+        }
+
+        let view = self.view();
+
+        lines
+            .into_iter()
+            .any(|n| before.get(n) != view.get(n).map(line_hash).as_ref())
+    }
+
+    // returns changed visible rows with their cells grouped into runs of the
+    // same pen, the rest of the changes stay pending for the next feed_str
+    pub fn feed_str_segments(&mut self, s: &str) -> Vec<(usize, Vec<Vec<Cell>>)> {
+        s.chars().for_each(|ch| self.feed(ch));
+        let (lines, _) = self.terminal.changes();
+        let view = self.view();
+
+        lines
+            .into_iter()
+            .map(|n| {
+                let segments = view[n].chunks(|c1, c2| c1.pen() != c2.pen()).collect();
+
+                (n, segments)
+            })
+            .collect()
+    }
+
+    // marks all visible rows as changed without touching their content, so
+    // the next feed_str/execute reports every row
+    pub fn touch_all(&mut self) {
+        self.terminal.touch_all();
+    }
+
+    pub fn execute(&mut self, fun: Function) -> Changes<'_> {
+        // VT52 mode lives in the parser, which the terminal has no access to
+        if matches!(&fun, Function::Decrst(modes) if modes.contains(&DecMode::Ansi)) {
+            self.parser.enter_vt52_mode();
+        }
+
+        self.terminal.execute(fun);
+
+        self.changes()
+    }
+
+    fn changes(&mut self) -> Changes<'_> {
         let (lines, resized) = self.terminal.changes();
+        let scrolled = self.terminal.take_scrolled_lines();
+        let response = self.terminal.take_response();
+        let buffer_transitions = self.terminal.take_buffer_transitions();
         let scrollback = self.terminal.gc();
 
         Changes {
             lines,
             resized,
+            scrolled,
+            response,
+            buffer_transitions,
             scrollback,
         }
     }
 
+    pub fn feed_events(&mut self, s: &str) -> Vec<VtEvent> {
+        self.terminal.take_title_changed();
+        self.terminal.take_bell();
+        let cursor = self.cursor();
+
+        let Changes {
+            lines,
+            resized,
+            scrolled,
+            response,
+            buffer_transitions,
+            ..
+        } = self.feed_str(s);
+
+        let mut events = Vec::new();
+
+        if resized {
+            let (cols, rows) = self.size();
+            events.push(VtEvent::Resized(cols, rows));
+        }
+
+        if scrolled > 0 {
+            events.push(VtEvent::ScrolledBack(scrolled));
+        }
+
+        events.extend(buffer_transitions.into_iter().map(VtEvent::BufferSwitched));
+
+        events.extend(lines.into_iter().map(VtEvent::LineChanged));
+
+        if self.cursor() != cursor {
+            events.push(VtEvent::CursorMoved);
+        }
+
+        if self.terminal.take_title_changed() {
+            events.push(VtEvent::TitleChanged);
+        }
+
+        if self.terminal.take_bell() {
+            events.push(VtEvent::Bell);
+        }
+
+        if let Some(response) = response {
+            events.push(VtEvent::Response(response));
+        }
+
+        events
+    }
+
     pub fn feed(&mut self, input: char) {
         if let Some(op) = self.parser.feed(input) {
             self.terminal.execute(op);
         }
     }
 
+    // feeds everything read until EOF, passing the changes from each read to
+    // on_changes
+    pub fn feed_reader<R: io::Read, F: FnMut(Changes<'_>)>(
+        &mut self,
+        reader: &mut R,
+        mut on_changes: F,
+    ) -> io::Result<usize> {
+        let mut buf = [0; 4096];
+        let mut pending = Vec::new();
+        let mut total = 0;
+
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            total += n;
+            pending.extend_from_slice(&buf[..n]);
+
+            loop {
+                match std::str::from_utf8(&pending) {
+                    Ok(s) => {
+                        s.chars().for_each(|ch| self.feed(ch));
+                        pending.clear();
+
+                        break;
+                    }
+
+                    Err(e) => {
+                        let valid = e.valid_up_to();
+                        let s = std::str::from_utf8(&pending[..valid]).unwrap();
+                        s.chars().for_each(|ch| self.feed(ch));
+
+                        match e.error_len() {
+                            Some(len) => {
+                                self.feed(char::REPLACEMENT_CHARACTER);
+                                pending.drain(..valid + len);
+                            }
+
+                            None => {
+                                // incomplete sequence at the end, wait for more bytes
+                                pending.drain(..valid);
+
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+
+            on_changes(self.changes());
+        }
+
+        if !pending.is_empty() {
+            self.feed(char::REPLACEMENT_CHARACTER);
+            on_changes(self.changes());
+        }
+
+        Ok(total)
+    }
+
     pub fn size(&self) -> (usize, usize) {
         (self.terminal.cols, self.terminal.rows)
     }
@@ -54,30 +241,414 @@ impl Vt {
         self.terminal.line(n)
     }
 
+    // indices into lines() of the visible view, everything before is scrollback
+    pub fn screen_range(&self) -> Range<usize> {
+        let len = self.lines().len();
+        let (_, rows) = self.size();
+
+        (len - rows)..len
+    }
+
+    // top and bottom rows of the visible screen (not scrollback)
+    pub fn top_line(&self) -> &Line {
+        &self.view()[0]
+    }
+
+    pub fn bottom_line(&self) -> &Line {
+        let view = self.view();
+
+        &view[view.len() - 1]
+    }
+
+    pub fn iter_cells(&self) -> impl Iterator<Item = (usize, usize, &Cell)> + '_ {
+        self.view().iter().enumerate().flat_map(|(row, line)| {
+            line.cells_with_cols()
+                .map(move |(col, cell)| (col, row, cell))
+        })
+    }
+
+    pub fn line_content_width(&self, n: usize) -> usize {
+        self.line(n).content_width()
+    }
+
+    pub fn char_at(&self, col: usize, row: usize) -> Option<char> {
+        self.view()
+            .get(row)
+            .and_then(|line| line.cells().get(col))
+            .map(|cell| cell.char())
+    }
+
+    pub fn word_at(&self, col: usize, row: usize) -> Option<(usize, usize, usize, String)> {
+        self.word_at_with(col, row, |ch| ch.is_alphanumeric() || ch == '_')
+    }
+
+    // Returns start col, end col (exclusive) and row of the word's first cell,
+    // plus its text. Wrapped lines are treated as continuous, so end col is
+    // counted from the start of the row and may exceed the number of columns.
+    pub fn word_at_with(
+        &self,
+        col: usize,
+        row: usize,
+        is_word_char: impl Fn(char) -> bool,
+    ) -> Option<(usize, usize, usize, String)> {
+        let view = self.view();
+        let cols = self.terminal.cols;
+
+        if row >= view.len() || col >= cols {
+            return None;
+        }
+
+        let mut first_row = row;
+
+        while first_row > 0 && view[first_row - 1].wrapped() {
+            first_row -= 1;
+        }
+
+        let mut last_row = row;
+
+        while last_row < view.len() - 1 && view[last_row].wrapped() {
+            last_row += 1;
+        }
+
+        let cells: Vec<&Cell> = view[first_row..=last_row]
+            .iter()
+            .flat_map(|line| line.cells())
+            .collect();
+
+        let is_word_cell = |cell: &Cell| cell.width() == 0 || is_word_char(cell.char());
+        let mut pos = (row - first_row) * cols + col;
+
+        // snap to the glyph the zero-width cell belongs to
+        while pos > 0 && cells[pos].width() == 0 {
+            pos -= 1;
+        }
+
+        if cells[pos].width() == 0 || !is_word_char(cells[pos].char()) {
+            return None;
+        }
+
+        let mut start = pos;
+
+        while start > 0 && is_word_cell(cells[start - 1]) {
+            start -= 1;
+        }
+
+        while cells[start].width() == 0 {
+            start += 1;
+        }
+
+        let mut end = pos + 1;
+
+        while end < cells.len() && is_word_cell(cells[end]) {
+            end += 1;
+        }
+
+        let mut text = String::new();
+
+        for cell in &cells[start..end] {
+            cell.push_text(&mut text);
+        }
+
+        let start_row = first_row + start / cols;
+        let start_col = start % cols;
+
+        Some((start_col, start_col + end - start, start_row, text))
+    }
+
+    pub fn unselectable_zones(&self) -> Vec<(usize, Range<usize>)> {
+        let mut zones = Vec::new();
+
+        for (row, line) in self.view().iter().enumerate() {
+            let mut start = None;
+
+            for (col, cell) in line.cells().iter().enumerate() {
+                match (cell.is_selectable(), start) {
+                    (false, None) => start = Some(col),
+
+                    (true, Some(s)) => {
+                        zones.push((row, s..col));
+                        start = None;
+                    }
+
+                    _ => (),
+                }
+            }
+
+            if let Some(s) = start {
+                zones.push((row, s..line.len()));
+            }
+        }
+
+        zones
+    }
+
+    pub fn is_line_blank(&self, n: usize) -> bool {
+        self.terminal.line(n).is_blank()
+    }
+
+    // text extraction:
+    // - text: logical lines (wrapped rows joined) of scrollback and view
+    // - screen_text: visible rows, each trimmed of trailing blanks
+    // - visible_string: visible rows as the full character grid, trimmed
+    //   only at the very end
+
     pub fn text(&self) -> Vec<String> {
         self.terminal.text()
     }
 
+    pub fn text_with_wraps(&self) -> Vec<(String, bool)> {
+        self.terminal.text_with_wraps()
+    }
+
+    pub fn screen_text(&self) -> String {
+        self.view()
+            .iter()
+            .map(|line| line.text().trim_end().to_owned())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // stable hash of visible cells (chars and pens), and optionally of the
+    // cursor, for cheap frame comparison
+    pub fn screen_hash(&self, with_cursor: bool) -> u64 {
+        let mut hasher = FnvHasher::new();
+
+        for cell in self.view().iter().flat_map(|line| line.cells()) {
+            hasher.cell(cell);
+        }
+
+        if with_cursor {
+            let cursor = self.cursor();
+            hasher.u64(cursor.col as u64);
+            hasher.u64(cursor.row as u64);
+            hasher.write(&[cursor.visible as u8]);
+        }
+
+        hasher.0
+    }
+
+    pub fn visible_string(&self) -> String {
+        let s = self
+            .view()
+            .iter()
+            .map(|line| line.text())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        s.trim_end().to_owned()
+    }
+
+    pub fn scrollback_len(&self) -> usize {
+        self.terminal.scrollback_len()
+    }
+
+    pub fn set_scrollback_limit(
+        &mut self,
+        limit: Option<usize>,
+    ) -> impl Iterator<Item = Line> + '_ {
+        self.terminal.set_scrollback_limit(limit)
+    }
+
+    pub fn title(&self) -> &str {
+        self.terminal.title()
+    }
+
     pub fn cursor(&self) -> Cursor {
         self.terminal.cursor()
     }
 
+    pub fn cursor_position(&self) -> Option<(usize, usize)> {
+        self.terminal.cursor().into()
+    }
+
+    // cell under the (visible) cursor, with pending wrap at the right margin
+    // mapped to the last column
+    pub fn cursor_cell(&self) -> Option<&Cell> {
+        let (col, row) = self.cursor_position()?;
+        let (cols, _) = self.size();
+
+        self.line(row).cells().get(col.min(cols - 1))
+    }
+
+    pub fn sixels(&self) -> &[Sixel] {
+        self.terminal.sixels()
+    }
+
     pub fn cursor_key_app_mode(&self) -> bool {
         self.terminal.cursor_keys_app_mode()
     }
 
+    pub fn cursor_blink(&self) -> bool {
+        self.terminal.cursor_blink()
+    }
+
+    pub fn sixel_display_mode(&self) -> bool {
+        self.terminal.sixel_display_mode()
+    }
+
+    pub fn private_color_registers(&self) -> bool {
+        self.terminal.private_color_registers()
+    }
+
+    pub fn reverse_screen_mode(&self) -> bool {
+        self.terminal.reverse_screen_mode()
+    }
+
+    pub fn origin_mode(&self) -> bool {
+        self.terminal.origin_mode()
+    }
+
+    pub fn scroll_region(&self) -> (usize, usize) {
+        self.terminal.scroll_region()
+    }
+
+    pub fn keypad_app_mode(&self) -> bool {
+        self.terminal.keypad_app_mode()
+    }
+
+    pub fn input_modes(&self) -> InputModes {
+        InputModes {
+            vt52_mode: self.parser.vt52_mode(),
+            ..self.terminal.input_modes()
+        }
+    }
+
+    pub fn encode_key(&self, key: Key, mods: Modifiers) -> Option<String> {
+        input::encode_key(key, mods, &self.input_modes())
+    }
+
+    pub fn take_apc_strings(&mut self) -> Vec<String> {
+        self.terminal.take_apc_strings()
+    }
+
+    pub fn take_unknown_sequences(&mut self) -> Vec<String> {
+        self.parser.take_unknown_sequences()
+    }
+
+    pub fn is_alternate_buffer(&self) -> bool {
+        self.terminal.is_alternate_buffer()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.terminal.generation()
+    }
+
+    pub fn parser_state(&self) -> State {
+        self.parser.state
+    }
+
+    pub fn vt52_mode(&self) -> bool {
+        self.parser.vt52_mode()
+    }
+
+    pub fn to_ansi(&self) -> String {
+        let view = self.view();
+        let mut s = String::new();
+
+        for (i, line) in view.iter().enumerate() {
+            s.push_str(&line.to_ansi());
+
+            if !line.wrapped() && i < view.len() - 1 {
+                s.push_str("\r\n");
+            }
+        }
+
+        s
+    }
+
+    #[cfg(feature = "html")]
+    pub fn to_html(&self, opts: HtmlOptions) -> String {
+        html::render(self.view(), &opts)
+    }
+
+    pub fn dump_line(&self, n: usize) -> Option<String> {
+        let view = self.view();
+        let line = view.get(n)?;
+        let mut seq = format!("\x1b[{};1H", n + 1);
+        seq.push_str(&line.dump());
+
+        if line.wrapped() && n < view.len() - 1 {
+            // print the first cell of the next row so the receiver marks this
+            // line as wrapped
+            seq.push_str(&view[n + 1].cells()[0].dump());
+        }
+
+        seq.push_str("\x1b[0m");
+
+        Some(seq)
+    }
+
     pub fn dump(&self) -> String {
         let mut seq = self.terminal.dump();
         seq.push_str(&self.parser.dump());
 
         seq
     }
+
+    #[cfg(feature = "binary")]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (cols, rows) = self.size();
+        let mut state = self.terminal.dump_state();
+        state.push_str(&self.parser.dump());
+
+        let mut encoder = Encoder::new();
+        encoder.varint(cols as u64);
+        encoder.varint(rows as u64);
+        encoder.width_mode(self.terminal.width_mode());
+        encoder.str(&state);
+        encoder.lines(self.terminal.primary_view());
+
+        if self.is_alternate_buffer() {
+            encoder.lines(self.view());
+        }
+
+        encoder.finish()
+    }
+
+    #[cfg(feature = "binary")]
+    pub fn from_bytes(bytes: &[u8]) -> Option<Vt> {
+        let mut decoder = Decoder::new(bytes)?;
+        let (cols, rows) = decoder.size()?;
+        let width_mode = decoder.width_mode()?;
+        let state = decoder.str()?;
+        let primary = decoder.lines(cols, rows)?;
+        let mut vt = Vt::builder()
+            .size(cols, rows)
+            .wide_chars(width_mode)
+            .build();
+        vt.feed_str(state);
+
+        let alternate = if vt.is_alternate_buffer() {
+            Some(decoder.lines(cols, rows)?)
+        } else {
+            None
+        };
+
+        if !decoder.is_empty() {
+            return None;
+        }
+
+        vt.terminal.restore_views(primary, alternate);
+
+        Some(vt)
+    }
 }
 
 pub struct Builder {
     size: (usize, usize),
     scrollback_limit: Option<usize>,
+    scrollback_slack: bool,
+    scrollback_reserve: usize,
     resizable: bool,
+    form_feed_clears: bool,
+    answerback: String,
+    cell_size: (usize, usize),
+    width_mode: WidthMode,
+    bce: bool,
+    alternate_screen: bool,
+    max_string_len: usize,
+    collect_unknown_sequences: bool,
+    collect_apc_strings: bool,
 }
 
 impl Builder {
@@ -93,17 +664,107 @@ impl Builder {
         self
     }
 
+    pub fn scrollback_slack(&mut self, slack: bool) -> &mut Self {
+        self.scrollback_slack = slack;
+
+        self
+    }
+
+    pub fn scrollback_reserve(&mut self, reserve: usize) -> &mut Self {
+        self.scrollback_reserve = reserve;
+
+        self
+    }
+
     pub fn resizable(&mut self, resizable: bool) -> &mut Self {
         self.resizable = resizable;
 
         self
     }
 
+    pub fn form_feed_clears(&mut self, clears: bool) -> &mut Self {
+        self.form_feed_clears = clears;
+
+        self
+    }
+
+    pub fn answerback(&mut self, answerback: String) -> &mut Self {
+        self.answerback = answerback;
+
+        self
+    }
+
+    pub fn cell_size(&mut self, width: usize, height: usize) -> &mut Self {
+        self.cell_size = (width, height);
+
+        self
+    }
+
+    pub fn wide_chars(&mut self, mode: WidthMode) -> &mut Self {
+        self.width_mode = mode;
+
+        self
+    }
+
+    pub fn bce(&mut self, bce: bool) -> &mut Self {
+        self.bce = bce;
+
+        self
+    }
+
+    pub fn alternate_screen(&mut self, alternate: bool) -> &mut Self {
+        self.alternate_screen = alternate;
+
+        self
+    }
+
+    pub fn max_string_len(&mut self, len: usize) -> &mut Self {
+        self.max_string_len = len;
+
+        self
+    }
+
+    pub fn collect_unknown_sequences(&mut self, collect: bool) -> &mut Self {
+        self.collect_unknown_sequences = collect;
+
+        self
+    }
+
+    pub fn collect_apc_strings(&mut self, collect: bool) -> &mut Self {
+        self.collect_apc_strings = collect;
+
+        self
+    }
+
     pub fn build(&self) -> Vt {
-        Vt {
-            parser: Parser::new(),
-            terminal: Terminal::new(self.size, self.scrollback_limit, self.resizable),
+        let mut terminal = Terminal::new(self.size, self.scrollback_limit, self.resizable);
+
+        terminal.set_scrollback_slack(self.scrollback_slack);
+        terminal.set_scrollback_reserve(self.scrollback_reserve);
+        terminal.set_form_feed_clears(self.form_feed_clears);
+        terminal.set_answerback(self.answerback.clone());
+
+        let (width, height) = self.cell_size;
+        terminal.set_cell_size(width, height);
+        terminal.set_width_mode(self.width_mode);
+        terminal.set_bce(self.bce);
+        terminal.set_collect_apc_strings(self.collect_apc_strings);
+
+        if self.alternate_screen {
+            terminal.start_on_alternate_screen();
         }
+
+        let mut parser = Parser::with_max_string_len(self.max_string_len);
+        parser.collect_unknown_sequences(self.collect_unknown_sequences);
+
+        Vt { parser, terminal }
+    }
+
+    pub fn build_from_dump(&self, dump: &str) -> Vt {
+        let mut vt = self.build();
+        vt.feed_str(dump);
+
+        vt
     }
 }
 
@@ -112,21 +773,108 @@ impl Default for Builder {
         Builder {
             size: (80, 24),
             scrollback_limit: None,
+            scrollback_slack: true,
+            scrollback_reserve: 1000,
             resizable: false,
+            form_feed_clears: false,
+            answerback: String::new(),
+            cell_size: (8, 16),
+            width_mode: WidthMode::Standard,
+            bce: true,
+            alternate_screen: false,
+            max_string_len: DEFAULT_MAX_STRING_LEN,
+            collect_unknown_sequences: false,
+            collect_apc_strings: false,
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VtEvent {
+    LineChanged(usize),
+    Resized(usize, usize),
+    ScrolledBack(usize),
+    BufferSwitched(BufferTransition),
+    TitleChanged,
+    Bell,
+    CursorMoved,
+    Response(String),
+}
+
 pub struct Changes<'a> {
     pub lines: Vec<usize>,
     pub resized: bool,
+    pub scrolled: usize,
+    pub response: Option<String>,
+    pub buffer_transitions: Vec<BufferTransition>,
     pub scrollback: Box<dyn Iterator<Item = Line> + 'a>,
 }
 
-#[cfg(test)]
+fn line_hash(line: &Line) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    line.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+// FNV-1a, for hashes which must stay the same across runs, builds and
+// platforms, so everything is fed as explicit little-endian bytes rather than
+// through Hash
+struct FnvHasher(u64);
+
+impl FnvHasher {
+    fn new() -> Self {
+        FnvHasher(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn cell(&mut self, cell: &Cell) {
+        self.write(&(cell.char() as u32).to_le_bytes());
+        self.u64(cell.combining().len() as u64);
+        self.write(cell.combining().as_bytes());
+        self.write(&[cell.width() as u8]);
+
+        let pen = cell.pen();
+        self.color(pen.foreground());
+        self.color(pen.background());
+
+        self.write(&[match pen.intensity() {
+            Intensity::Normal => 0,
+            Intensity::Bold => 1,
+            Intensity::Faint => 2,
+        }]);
+
+        self.write(&[pen.attr_bits()]);
+    }
+
+    fn color(&mut self, color: Option<Color>) {
+        match color {
+            None => self.write(&[0]),
+            Some(Color::Indexed(c)) => self.write(&[1, c]),
+            Some(Color::RGB(c)) => self.write(&[2, c.r, c.g, c.b]),
+        }
+    }
+}
+
+#[cfg(test)]
 mod tests {
-    use super::Vt;
+    use super::{BufferTransition, Changes, Vt, VtEvent};
+    use crate::cell::WidthMode;
+    use crate::color::Color;
+    use crate::input::{Key, Modifiers};
     use crate::line::Line;
+    use crate::parser::{DecMode, Function, State};
+    use crate::terminal::{MouseEncoding, MouseMode};
     use pretty_assertions::assert_eq;
     use proptest::prelude::*;
     use std::env;
@@ -151,6 +899,16 @@ mod tests {
         vt.feed_str("abcdef");
 
         assert_eq!(text(&vt), "abc|f\n\n\n");
+
+        // no auto wrap, wide chars
+
+        let mut vt = Vt::new(4, 4);
+
+        vt.feed_str("\x1b[?7l");
+        vt.feed_str("ab\u{3042}\u{3044}\u{3046}");
+
+        assert_eq!(text(&vt), "ab\u{3042}|\u{3046}\n\n\n");
+        assert_eq!(vt.line(0).len(), 4);
     }
 
     #[test]
@@ -176,918 +934,2837 @@ mod tests {
 
         // custom bottom margin, print below it
 
-        let mut vt = Vt::new(4, 6);
+        let mut vt = Vt::new(4, 6);
+
+        let input = "\x1b[;3rxxxxxxxxxx\x1b[50;1Hyyy\x1b[50Czzz";
+
+        vt.feed_str(input);
+
+        assert_eq!(text(&vt), "xxxx\nxxxx\nxx\n\n\nzz|yz");
+    }
+
+    #[test]
+    fn ignore_del() {
+        let mut vt = Vt::new(4, 2);
+
+        vt.feed_str("a\x7fb");
+
+        assert_eq!(text(&vt), "ab|\n");
+    }
+
+    #[test]
+    fn execute_cr_at_pending_wrap() {
+        let mut vt = Vt::new(4, 2);
+
+        vt.feed_str("abcd\rx");
+
+        assert_eq!(text(&vt), "x|bcd\n");
+        assert_eq!(wrapped(&vt), vec![false, false]);
+    }
+
+    #[test]
+    fn execute_lf() {
+        let mut vt = build_vt(8, 2, 3, 0, "abc");
+
+        vt.feed_str("\n");
+
+        assert_eq!(vt.cursor(), (3, 1));
+        assert_eq!(text(&vt), "abc\n   |");
+
+        vt.feed_str("d\n");
+
+        assert_eq!(vt.cursor(), (4, 1));
+        assert_eq!(text(&vt), "   d\n    |");
+    }
+
+    #[test]
+    fn execute_lf_background_color_erase() {
+        let mut vt = Vt::new(4, 3);
+
+        vt.feed_str("a\x1b[44m\n\n\n\n\x1b[41m\n");
+
+        let bg = |row: usize| {
+            vt.line(row)
+                .cells()
+                .iter()
+                .map(|c| c.pen().background())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(bg(0), vec![Some(Color::Indexed(4)); 4]);
+        assert_eq!(bg(1), vec![Some(Color::Indexed(4)); 4]);
+        assert_eq!(bg(2), vec![Some(Color::Indexed(1)); 4]);
+
+        let mut vt2 = Vt::new(4, 3);
+        vt2.feed_str(&vt.dump());
+
+        assert_vts_eq(&vt, &vt2);
+    }
+
+    #[test]
+    fn execute_erase_without_bce() {
+        let bgs = |bce: bool| {
+            let mut vt = Vt::builder().size(4, 3).bce(bce).build();
+            vt.feed_str("abcd\x1b[44m\x1b[S\x1b[1;1H\x1b[K\x1b[2;2H\x1b[P");
+
+            (0..3)
+                .map(|row| vt.line(row).cells()[3].pen().background())
+                .collect::<Vec<_>>()
+        };
+
+        let blue = Some(Color::Indexed(4));
+
+        assert_eq!(bgs(true), vec![blue, blue, blue]);
+        assert_eq!(bgs(false), vec![None, None, None]);
+    }
+
+    #[test]
+    fn execute_lf_vt_ff_in_new_line_mode() {
+        let mut vt = build_vt(8, 4, 3, 0, "abc");
+
+        vt.feed_str("\x1b[20h\n");
+
+        assert_eq!(vt.cursor(), (0, 1));
+
+        vt.feed_str("de\x0b");
+
+        assert_eq!(vt.cursor(), (0, 2));
+
+        vt.feed_str("fg\x0c");
+
+        assert_eq!(vt.cursor(), (0, 3));
+        assert_eq!(text(&vt), "abc\nde\nfg\n|");
+
+        vt.feed_str("\x1b[20lh\x0b");
+
+        assert_eq!(vt.cursor(), (1, 3));
+    }
+
+    #[test]
+    fn execute_ff() {
+        let mut vt = build_vt(8, 2, 3, 0, "abc");
+
+        vt.feed_str("\x0c");
+
+        assert_eq!(text(&vt), "abc\n   |");
+
+        let mut vt = Vt::builder().size(8, 2).form_feed_clears(true).build();
+
+        vt.feed_str("abc\r\ndef\x0cg");
+
+        assert_eq!(text(&vt), "g|\n");
+    }
+
+    #[test]
+    fn execute_ri() {
+        let mut vt = build_vt(8, 5, 0, 0, "abcd\r\nefgh\r\nijkl\r\nmnop\r\nqrst");
+
+        vt.feed_str("\x1bM"); // RI
+
+        assert_eq!(text(&vt), "|\nabcd\nefgh\nijkl\nmnop");
+
+        vt.feed_str("\x1b[3;4r"); // use smaller scroll region
+        vt.feed_str("\x1b[3;1H"); // place cursor on top margin
+        vt.feed_str("\x1bM"); // RI
+
+        assert_eq!(text(&vt), "\nabcd\n|\nefgh\nmnop");
+    }
+
+    #[test]
+    fn execute_su() {
+        // short lines, default margins
+
+        let mut vt = Vt::new(4, 6);
+        vt.feed_str("aa\r\nbb\r\ncc\r\ndd\r\nee\r\nff");
+        vt.feed_str("\x1b[2S");
+        assert_eq!(text(&vt), "cc\ndd\nee\nff\n\n  |");
+
+        // short lines, margins at 1 (top) and 4 (bottom)
+
+        let mut vt = Vt::new(4, 6);
+
+        vt.feed_str("aa\r\nbb\r\ncc\r\ndd\r\nee\r\nff");
+        vt.feed_str("\x1b[2;5r");
+        vt.feed_str("\x1b[1;1H");
+        vt.feed_str("\x1b[2S");
+
+        assert_eq!(text(&vt), "|aa\ndd\nee\n\n\nff");
+
+        // wrapped lines, default margins
+
+        let mut vt = Vt::new(4, 6);
+
+        vt.feed_str("aaaaaa\r\nbbbbbb\r\ncccccc");
+        vt.feed_str("\x1b[2S");
+
+        assert_eq!(text(&vt), "bbbb\nbb\ncccc\ncc\n\n  |");
+        assert_eq!(wrapped(&vt), vec![true, false, true, false, false, false]);
+
+        // wrapped lines, margins at 1 (top) and 4 (bottom)
+
+        let mut vt = Vt::new(4, 6);
+
+        vt.feed_str("aaaaaa\r\nbbbbbb\r\ncccccc");
+        vt.feed_str("\x1b[2;5r");
+        vt.feed_str("\x1b[1;1H");
+        vt.feed_str("\x1b[2S");
+
+        assert_eq!(text(&vt), "|aaaa\nbb\ncccc\n\n\ncc");
+        assert_eq!(wrapped(&vt), vec![false, false, false, false, false, false]);
+    }
+
+    #[test]
+    fn execute_sd() {
+        // short lines, default margins
+
+        let mut vt = Vt::new(4, 6);
+
+        vt.feed_str("aa\r\nbb\r\ncc\r\ndd\r\nee\r\nff");
+        vt.feed_str("\x1b[2T");
+
+        assert_eq!(text(&vt), "\n\naa\nbb\ncc\ndd|");
+
+        // short lines, margins at 1 (top) and 4 (bottom)
+
+        let mut vt = Vt::new(4, 6);
+
+        vt.feed_str("aa\r\nbb\r\ncc\r\ndd\r\nee\r\nff");
+        vt.feed_str("\x1b[2;5r");
+        vt.feed_str("\x1b[1;1H");
+        vt.feed_str("\x1b[2T");
+
+        assert_eq!(text(&vt), "|aa\n\n\nbb\ncc\nff");
+
+        // wrapped lines, default margins
+
+        let mut vt = Vt::new(4, 6);
+
+        vt.feed_str("aaaaaa\r\nbbbbbb\r\ncccccc");
+        vt.feed_str("\x1b[2T");
+
+        assert_eq!(text(&vt), "\n\naaaa\naa\nbbbb\nbb|");
+        assert_eq!(wrapped(&vt), vec![false, false, true, false, true, false]);
+
+        // wrapped lines, margins at 1 (top) and 4 (bottom)
+
+        let mut vt = Vt::new(4, 6);
+
+        vt.feed_str("aaaaaa\r\nbbbbbb\r\ncccccc");
+        vt.feed_str("\x1b[2;5r");
+        vt.feed_str("\x1b[1;1H");
+        vt.feed_str("\x1b[2T");
+
+        assert_eq!(text(&vt), "|aaaa\n\n\naa\nbbbb\ncc");
+        assert_eq!(wrapped(&vt), vec![false, false, false, false, false, false]);
+    }
+
+    #[test]
+    fn execute_su_sd_count_exceeding_region() {
+        let input = "aa\r\nbb\r\ncc\r\ndd\r\nee\r\nff\x1b[2;5r\x1b[1;1H";
+
+        let mut vt = Vt::new(4, 6);
+        vt.feed_str(input);
+        vt.feed_str("\x1b[999S");
+
+        assert_eq!(text(&vt), "|aa\n\n\n\n\nff");
+        assert_eq!(vt.scrollback_len(), 0);
+
+        let mut vt = Vt::new(4, 6);
+        vt.feed_str(input);
+        vt.feed_str("\x1b[999T");
+
+        assert_eq!(text(&vt), "|aa\n\n\n\n\nff");
+        assert_eq!(vt.scrollback_len(), 0);
+
+        // region starting at the top pushes at most its own lines into
+        // scrollback
+
+        let mut vt = Vt::new(4, 6);
+        vt.feed_str("aa\r\nbb\r\ncc\r\ndd\r\nee\r\nff\x1b[1;3r\x1b[999S");
+
+        assert_eq!(text(&vt), "|\n\n\ndd\nee\nff");
+        assert_eq!(vt.scrollback_len(), 3);
+
+        let mut vt = Vt::new(4, 6);
+        vt.feed_str("aa\r\nbb\r\ncc\r\ndd\r\nee\r\nff\x1b[999S");
+
+        assert_eq!(text(&vt), "\n\n\n\n\n  |");
+        assert_eq!(vt.scrollback_len(), 6);
+    }
+
+    #[test]
+    fn execute_bs() {
+        let mut vt = Vt::new(4, 2);
+
+        vt.feed_str("a");
+        vt.feed_str("\x08");
+
+        assert_eq!(text(&vt), "|a\n");
+
+        vt.feed_str("\x08");
+
+        assert_eq!(text(&vt), "|a\n");
+
+        vt.feed_str("abcd");
+        vt.feed_str("\x08");
+
+        assert_eq!(text(&vt), "ab|cd\n");
+
+        vt.feed_str("cdef");
+        vt.feed_str("\x08");
+
+        assert_eq!(text(&vt), "abcd\ne|f");
+
+        vt.feed_str("\x08");
+
+        assert_eq!(text(&vt), "abcd\n|ef");
+
+        vt.feed_str("\x08");
+
+        assert_eq!(text(&vt), "abcd\n|ef");
+    }
+
+    #[test]
+    fn execute_bs_after_last_column() {
+        let mut vt = Vt::new(4, 2);
+
+        vt.feed_str("abcd\x08");
+
+        assert_eq!(text(&vt), "ab|cd\n");
+
+        vt.feed_str("x");
+
+        assert_eq!(text(&vt), "abx|d\n");
+
+        vt.feed_str("y\x08\x08");
+
+        assert_eq!(text(&vt), "a|bxy\n");
+
+        let mut vt = Vt::new(4, 2);
+
+        vt.feed_str("\x1b[?7labcd\x08");
+
+        assert_eq!(text(&vt), "ab|cd\n");
+
+        vt.feed_str("xyz");
+
+        assert_eq!(text(&vt), "abx|z\n");
+    }
+
+    #[test]
+    fn execute_cup() {
+        let mut vt = Vt::new(4, 2);
+
+        vt.feed_str("abc\r\ndef");
+        vt.feed_str("\x1b[1;1;H");
+
+        assert_eq!(vt.cursor(), (0, 0));
+
+        vt.feed_str("\x1b[10;10;H");
+
+        assert_eq!(vt.cursor(), (3, 1));
+
+        vt.feed_str("\x1b[1;1H\x1b[99999999;99999999H");
+
+        assert_eq!(vt.cursor(), (3, 1));
+
+        let mut vt = Vt::new(8, 6);
+
+        vt.feed_str("\x1b[;5H");
+
+        assert_eq!(vt.cursor(), (4, 0));
+
+        vt.feed_str("\x1b[5;H");
+
+        assert_eq!(vt.cursor(), (0, 4));
+
+        vt.feed_str("\x1b[3;3H\x1b[H");
+
+        assert_eq!(vt.cursor(), (0, 0));
+
+        vt.feed_str("\x1b[;7f");
+
+        assert_eq!(vt.cursor(), (6, 0));
+
+        vt.feed_str("\x1b[4f");
+
+        assert_eq!(vt.cursor(), (0, 3));
+    }
+
+    #[test]
+    fn execute_dsr() {
+        let mut vt = Vt::new(8, 6);
+
+        assert_eq!(vt.feed_str("\x1b[5n").response.as_deref(), Some("\x1b[0n"));
+        assert_eq!(vt.feed_str("abc").response, None);
+
+        let response = vt.feed_str("\x1b[3;5H\x1b[6n").response;
+
+        assert_eq!(response.as_deref(), Some("\x1b[3;5R"));
+
+        // origin mode, position relative to top margin
+
+        vt.feed_str("\x1b[2;5r\x1b[?6h\x1b[2;3H");
+
+        assert_eq!(vt.cursor(), (2, 2));
+
+        let response = vt.feed_str("\x1b[6n").response;
+
+        assert_eq!(response.as_deref(), Some("\x1b[2;3R"));
+
+        vt.feed_str("\x1b[4d");
+
+        assert_eq!(vt.cursor(), (2, 4));
+
+        let response = vt.feed_str("\x1b[6n").response;
+
+        assert_eq!(response.as_deref(), Some("\x1b[4;3R"));
+
+        // pending wrap reports the last column
+
+        let mut vt = Vt::new(4, 2);
+
+        let response = vt.feed_str("abcd\x1b[6n").response;
+
+        assert_eq!(response.as_deref(), Some("\x1b[1;4R"));
+    }
+
+    #[test]
+    fn execute_decrqss() {
+        let mut vt = Vt::new(8, 6);
+
+        let response = vt.feed_str("\x1bP$qm\x1b\\").response;
+
+        assert_eq!(response.as_deref(), Some("\x1bP1$r0m\x1b\\"));
+
+        let response = vt.feed_str("\x1b[1;31m\x1bP$qm\x1b\\").response;
+
+        assert_eq!(response.as_deref(), Some("\x1bP1$r0;31;1m\x1b\\"));
+
+        let response = vt.feed_str("\x1bP$qr\x1b\\").response;
+
+        assert_eq!(response.as_deref(), Some("\x1bP1$r1;6r\x1b\\"));
+
+        let response = vt.feed_str("\x1b[2;4r\u{90}$qr\u{9c}").response;
+
+        assert_eq!(response.as_deref(), Some("\x1bP1$r2;4r\x1b\\"));
+
+        let response = vt.feed_str("\x1bP$qx\x1b\\").response;
+
+        assert_eq!(response.as_deref(), Some("\x1bP0$r\x1b\\"));
+    }
+
+    #[test]
+    fn execute_s8c1t() {
+        let mut vt = Vt::new(8, 6);
+
+        vt.feed_str("\x1b G");
+
+        let response = vt.feed_str("\x1b[5n\x1bP$qr\x1b\\").response;
+
+        assert_eq!(response.as_deref(), Some("\u{9b}0n\u{90}1$r1;6r\u{9c}"));
+
+        let mut vt2 = Vt::new(8, 6);
+        vt2.feed_str(&vt.dump());
+
+        assert_vts_eq(&vt, &vt2);
+
+        vt.feed_str("\x1b F");
+
+        let response = vt.feed_str("\x1b[6n").response;
+
+        assert_eq!(response.as_deref(), Some("\x1b[1;1R"));
+    }
+
+    #[test]
+    fn print_combining_chars() {
+        let mut vt = Vt::new(6, 2);
+
+        vt.feed_str("e\u{301}a\u{300}\u{323}x");
+
+        assert_eq!(vt.cursor(), (3, 0));
+        assert_eq!(vt.line(0).cells()[0].combining(), "\u{301}");
+        assert_eq!(vt.line(0).cells()[1].text(), "a\u{300}\u{323}");
+        assert_eq!(text(&vt), "e\u{301}a\u{300}\u{323}x|\n");
+
+        // emoji ZWJ sequence with variation selector
+
+        let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+        vt.feed_str(&format!("\u{2764}\u{fe0f}{family}"));
+
+        assert_eq!(vt.cursor(), (5, 0));
+        assert_eq!(vt.line(0).cells()[3].text(), "\u{2764}\u{fe0f}");
+        assert_eq!(vt.line(0).cells()[4].text(), family);
+
+        // combining char at pending wrap attaches to the last column
+
+        vt.feed_str("z\u{308}");
+
+        assert_eq!(vt.cursor(), (6, 0));
+        assert_eq!(vt.line(0).cells()[5].text(), "z\u{308}");
+        assert_eq!(wrapped(&vt), vec![false, false]);
+
+        let mut vt2 = Vt::new(6, 2);
+        vt2.feed_str(&vt.dump());
+
+        assert_vts_eq(&vt, &vt2);
+    }
+
+    #[test]
+    fn print_combining_chars_limit() {
+        let mut vt = Vt::new(6, 2);
+
+        vt.feed_str(&format!("a{}b", "\u{301}".repeat(80_000)));
+
+        assert_eq!(vt.cursor(), (2, 0));
+        assert_eq!(vt.line(0).cells()[0].combining(), "\u{301}".repeat(16));
+        assert_eq!(vt.line(0).cells()[1].text(), "b");
+    }
+
+    #[test]
+    fn print_trailing_zwj() {
+        let mut vt = Vt::new(4, 1);
+
+        vt.feed_str(" \u{200d}");
+
+        let mut vt2 = Vt::new(4, 1);
+        vt2.feed_str(&vt.dump());
+
+        assert_vts_eq(&vt, &vt2);
+
+        vt.feed_str("a");
+
+        assert_eq!(vt.cursor(), (2, 0));
+        assert_eq!(vt.line(0).cells()[1].text(), "a");
+    }
+
+    #[test]
+    fn print_zero_width_format_controls() {
+        let mut vt = Vt::new(6, 2);
+
+        vt.feed_str("a\u{200b}b\u{ad}c\u{feff}\u{2060}d");
+
+        assert_eq!(vt.cursor(), (4, 0));
+        assert_eq!(vt.line(0).cells()[0].text(), "a\u{200b}");
+        assert_eq!(vt.line(0).cells()[1].text(), "b\u{ad}");
+        assert_eq!(vt.line(0).cells()[2].text(), "c\u{feff}\u{2060}");
+        assert_eq!(vt.line(0).width(), 6);
+
+        // at the start of a line there's nothing to attach to
+
+        vt.feed_str("\r\n\u{200b}\u{301}x\u{200b}");
+
+        assert_eq!(vt.cursor(), (1, 1));
+        assert_eq!(vt.line(1).cells()[0].text(), "x\u{200b}");
+
+        let mut vt2 = Vt::new(6, 2);
+        vt2.feed_str(&vt.dump());
+
+        assert_vts_eq(&vt, &vt2);
+    }
+
+    #[test]
+    fn print_width_modes() {
+        let widths = |mode: WidthMode| {
+            let mut vt = Vt::builder().size(10, 1).wide_chars(mode).build();
+            vt.feed_str("a\u{b1}\u{263a}\u{2764}\u{fe0f}\u{1f600}");
+
+            vt.line(0)
+                .cells()
+                .iter()
+                .take(5)
+                .map(|c| c.width())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(widths(WidthMode::Standard), vec![1, 1, 1, 1, 2]);
+        assert_eq!(widths(WidthMode::AmbiguousWide), vec![1, 2, 1, 1, 2]);
+        assert_eq!(widths(WidthMode::EmojiWide), vec![1, 1, 2, 2, 2]);
+
+        let mut vt = Vt::builder()
+            .size(10, 1)
+            .wide_chars(WidthMode::EmojiWide)
+            .build();
+
+        vt.feed_str("\u{2764}\u{fe0f}x");
+
+        assert_eq!(vt.line(0).content_width(), 3);
+    }
+
+    #[test]
+    fn iter_cells() {
+        let mut vt = Vt::new(3, 2);
+
+        vt.feed_str("a\u{301}b\r\nc");
+
+        let cells: Vec<(usize, usize, String)> = vt
+            .iter_cells()
+            .map(|(c, r, cell)| (c, r, cell.text()))
+            .collect();
+
+        assert_eq!(
+            cells,
+            vec![
+                (0, 0, "a\u{301}".to_owned()),
+                (1, 0, "b".to_owned()),
+                (2, 0, " ".to_owned()),
+                (0, 1, "c".to_owned()),
+                (1, 1, " ".to_owned()),
+                (2, 1, " ".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn execute_function() {
+        let mut vt = Vt::new(8, 3);
+
+        vt.execute(Function::Cup(2, 3));
+        let lines = vt.execute(Function::Print('a')).lines;
+
+        assert_eq!(lines, vec![1]);
+        assert_eq!(text(&vt), "\n  a|\n");
+    }
+
+    #[test]
+    fn execute_enq() {
+        let mut vt = Vt::new(8, 2);
+
+        assert_eq!(vt.feed_str("\x05").response, None);
+
+        let mut vt = Vt::builder()
+            .size(8, 2)
+            .answerback("avt".to_owned())
+            .build();
+
+        assert_eq!(vt.feed_str("a\x05b").response.as_deref(), Some("avt"));
+        assert_eq!(text(&vt), "ab|\n");
+    }
+
+    #[test]
+    fn execute_xtwinops_reports() {
+        let mut vt = Vt::builder().size(80, 24).cell_size(10, 20).build();
+        let generation = vt.generation();
+
+        assert_eq!(
+            vt.feed_str("\x1b[18t").response.as_deref(),
+            Some("\x1b[8;24;80t")
+        );
+        assert_eq!(
+            vt.feed_str("\x1b[19t").response.as_deref(),
+            Some("\x1b[9;24;80t")
+        );
+        assert_eq!(
+            vt.feed_str("\x1b[14t").response.as_deref(),
+            Some("\x1b[4;480;800t")
+        );
+        assert_eq!(vt.feed_str("\x1b[11t").response.as_deref(), Some("\x1b[1t"));
+        assert_eq!(
+            vt.feed_str("\x1b[13t").response.as_deref(),
+            Some("\x1b[3;0;0t")
+        );
+        assert_eq!(vt.feed_str("\x1b[12t").response, None);
+        assert_eq!(vt.generation(), generation);
+
+        let mut vt = Vt::builder().size(80, 24).resizable(true).build();
+        vt.feed_str("\x1b[8;10;40t");
+
+        assert_eq!(
+            vt.feed_str("\x1b[18t").response.as_deref(),
+            Some("\x1b[8;10;40t")
+        );
+        assert_eq!(
+            vt.feed_str("\x1b[14t").response.as_deref(),
+            Some("\x1b[4;160;320t")
+        );
+    }
+
+    #[test]
+    fn execute_decaln() {
+        let mut vt = Vt::new(3, 3);
+
+        vt.feed_str("abcdef\x1b[1;2r\x1b[3;2H\x1b[31m\x1b#8");
+
+        assert_eq!(text(&vt), "|EEE\nEEE\nEEE");
+        assert_eq!(wrapped(&vt), vec![false, false, false]);
+        assert!(vt.line(0).cells().iter().all(|c| c.pen().is_default()));
+
+        vt.feed_str("\x1b[3;1H\n");
+
+        assert_eq!(text(&vt), "EEE\nEEE\n|");
+    }
+
+    #[test]
+    fn execute_cuu() {
+        let mut vt = Vt::new(8, 4);
+
+        vt.feed_str("abcd\n\n\n");
+        vt.feed_str("\x1b[A");
+
+        assert_eq!(vt.cursor(), (4, 2));
+
+        vt.feed_str("\x1b[2A");
+
+        assert_eq!(vt.cursor(), (4, 0));
+    }
+
+    #[test]
+    fn execute_cuu_cud_outside_margins() {
+        // margins at rows 2..=4 of 6
+
+        let mut vt = Vt::new(4, 6);
+        vt.feed_str("\x1b[3;5r");
+
+        // below the bottom margin CUU moves freely down there, and stops at the
+        // top margin once inside the region
+
+        vt.feed_str("\x1b[6;1H\x1b[A");
+
+        assert_eq!(vt.cursor(), (0, 4));
+
+        vt.feed_str("\x1b[6;1H\x1b[99A");
+
+        assert_eq!(vt.cursor(), (0, 2));
+
+        // below the bottom margin CUD stops at the last row
+
+        vt.feed_str("\x1b[6;1H\x1b[99B");
+
+        assert_eq!(vt.cursor(), (0, 5));
+
+        // above the top margin CUD stops at the bottom margin
+
+        vt.feed_str("\x1b[1;1H\x1b[B");
+
+        assert_eq!(vt.cursor(), (0, 1));
+
+        vt.feed_str("\x1b[1;1H\x1b[99B");
+
+        assert_eq!(vt.cursor(), (0, 4));
+
+        // above the top margin CUU stops at the first row
+
+        vt.feed_str("\x1b[2;1H\x1b[99A");
+
+        assert_eq!(vt.cursor(), (0, 0));
+
+        // on the margins themselves
+
+        vt.feed_str("\x1b[5;1H\x1b[99B");
+
+        assert_eq!(vt.cursor(), (0, 4));
+
+        vt.feed_str("\x1b[3;1H\x1b[99A");
+
+        assert_eq!(vt.cursor(), (0, 2));
+    }
+
+    #[test]
+    fn execute_cpl() {
+        let mut vt = Vt::new(8, 4);
+
+        vt.feed_str("abcd\r\n\r\n\r\nef");
+
+        assert_eq!(vt.cursor(), (2, 3));
+
+        vt.feed_str("\x1b[F");
+
+        assert_eq!(vt.cursor(), (0, 2));
+
+        vt.feed_str("\x1b[2F");
+
+        assert_eq!(vt.cursor(), (0, 0));
+    }
+
+    #[test]
+    fn execute_cnl() {
+        let mut vt = Vt::new(4, 4);
+
+        vt.feed_str("ab");
+        vt.feed_str("\x1b[E");
+
+        assert_eq!(vt.cursor(), (0, 1));
+
+        vt.feed_str("\x1b[3E");
+
+        assert_eq!(vt.cursor(), (0, 3));
+    }
+
+    #[test]
+    fn execute_vpa() {
+        let mut vt = Vt::new(4, 4);
+
+        vt.feed_str("\r\n\r\naaa\r\nbbb");
+        vt.feed_str("\x1b[d");
+
+        assert_eq!(vt.cursor(), (3, 0));
+
+        vt.feed_str("\x1b[10d");
+
+        assert_eq!(vt.cursor(), (3, 3));
+    }
+
+    #[test]
+    fn execute_cud() {
+        let mut vt = Vt::new(8, 4);
+
+        vt.feed_str("abcd");
+        vt.feed_str("\x1b[B");
+
+        assert_eq!(text(&vt), "abcd\n    |\n\n");
+
+        vt.feed_str("\x1b[2B");
+
+        assert_eq!(text(&vt), "abcd\n\n\n    |");
+    }
+
+    #[test]
+    fn execute_cuf() {
+        let mut vt = Vt::new(4, 1);
+
+        vt.feed_str("\x1b[2C");
+
+        assert_eq!(text(&vt), "  |");
+
+        vt.feed_str("\x1b[2C");
+
+        assert_eq!(text(&vt), "   |");
+
+        vt.feed_str("a");
+
+        assert_eq!(text(&vt), "   a|");
+
+        vt.feed_str("\x1b[5C");
+
+        assert_eq!(text(&vt), "   |a");
+
+        vt.feed_str("ab");
+        vt.feed_str("\x1b[10C");
+
+        assert_eq!(text(&vt), "b  |");
+    }
+
+    #[test]
+    fn execute_cub() {
+        let mut vt = Vt::new(8, 2);
+
+        vt.feed_str("abcd");
+        vt.feed_str("\x1b[2D");
+
+        assert_eq!(text(&vt), "ab|cd\n");
+
+        vt.feed_str("cdef");
+        vt.feed_str("\x1b[2D");
+
+        assert_eq!(text(&vt), "abcd|ef\n");
+
+        vt.feed_str("\x1b[10D");
+
+        assert_eq!(text(&vt), "|abcdef\n");
+
+        let mut vt = Vt::new(4, 2);
+
+        vt.feed_str("abcd");
+        vt.feed_str("\x1b[D");
+
+        assert_eq!(text(&vt), "ab|cd\n");
+    }
+
+    #[test]
+    fn execute_ich() {
+        let mut vt = build_vt(8, 2, 3, 0, "abcdefghijklmn");
+
+        vt.feed_str("\x1b[@");
+
+        assert_eq!(text(&vt), "abc| defg\nijklmn");
+        assert_eq!(wrapped(&vt), vec![true, false]);
+
+        vt.feed_str("\x1b[2@");
+
+        assert_eq!(text(&vt), "abc|   de\nijklmn");
+
+        vt.feed_str("\x1b[10@");
+
+        assert_eq!(text(&vt), "abc|\nijklmn");
+
+        let mut vt = build_vt(8, 2, 7, 0, "abcdefghijklmn");
+
+        vt.feed_str("\x1b[10@");
+        assert_eq!(text(&vt), "abcdefg|\nijklmn");
+
+        // pending wrap after exact fill
+
+        let mut vt = Vt::new(8, 2);
+
+        vt.feed_str("abcdefgh\x1b[@");
+
+        assert_eq!(text(&vt), "abcdefg|\n");
+
+        vt.feed_str("x");
+
+        assert_eq!(text(&vt), "abcdefgx|\n");
+        assert_eq!(wrapped(&vt), vec![false, false]);
+    }
+
+    #[test]
+    fn insert_mode() {
+        let mut vt = build_vt(8, 2, 2, 0, "abcdef");
+
+        vt.feed_str("\x1b[4hx");
+
+        assert_eq!(text(&vt), "abx|cdef\n");
+
+        vt.feed_str("\u{3042}");
+
+        assert_eq!(text(&vt), "abx\u{3042}|cdef\n");
+        assert_eq!(vt.line(0).len(), 8);
+
+        vt.feed_str("\x1b[4ly");
+
+        assert_eq!(text(&vt), "abx\u{3042}y|def\n");
+    }
+
+    #[test]
+    fn execute_il() {
+        let mut vt = build_vt(4, 4, 2, 1, "abcdefghij");
+
+        vt.feed_str("\x1b[L");
+
+        assert_eq!(text(&vt), "abcd\n  |\nefgh\nij");
+        assert_eq!(wrapped(&vt), vec![false, false, true, false]);
+
+        vt.feed_str("\x1b[A");
+        vt.feed_str("\x1b[L");
+
+        assert_eq!(text(&vt), "  |\nabcd\n\nefgh");
+        assert_eq!(wrapped(&vt), vec![false, false, false, false]);
+
+        vt.feed_str("\x1b[3B");
+        vt.feed_str("\x1b[100L");
+
+        assert_eq!(text(&vt), "\nabcd\n\n  |");
+
+        // cursor outside of scroll region
+
+        let mut vt = Vt::new(4, 4);
+
+        vt.feed_str("abcdefghijklmn");
+        vt.feed_str("\x1b[2;3r");
+        vt.feed_str("\x1b[4;1H");
+        vt.feed_str("\x1b[L");
+
+        assert_eq!(text(&vt), "abcd\nefgh\nijkl\n|mn");
+
+        vt.feed_str("\x1b[1;1H");
+        vt.feed_str("\x1b[L");
+
+        assert_eq!(text(&vt), "|abcd\nefgh\nijkl\nmn");
+    }
+
+    #[test]
+    fn execute_dl() {
+        let mut vt = Vt::new(4, 4);
+
+        vt.feed_str("abcdefghijklmn");
+        vt.feed_str("\x1b[2A");
+        vt.feed_str("\x1b[M");
+
+        assert_eq!(text(&vt), "abcd\nij|kl\nmn\n");
+        assert_eq!(wrapped(&vt), vec![false, true, false, false]);
+
+        // cursor above bottom margin
+
+        let mut vt = Vt::new(4, 4);
+
+        vt.feed_str("abcdefghijklmn");
+        vt.feed_str("\x1b[1;3r");
+        vt.feed_str("\x1b[2;1H");
+        vt.feed_str("\x1b[M");
+
+        assert_eq!(text(&vt), "abcd\n|ijkl\n\nmn");
+        assert_eq!(wrapped(&vt), vec![false, false, false, false]);
+
+        // cursor below bottom margin
+
+        let mut vt = Vt::new(4, 4);
+
+        vt.feed_str("abcdefghijklmn");
+        vt.feed_str("\x1b[1;2r");
+        vt.feed_str("\x1b[4;1H");
+        vt.feed_str("\x1b[M");
+
+        assert_eq!(text(&vt), "abcd\nefgh\nijkl\n|mn");
+        assert_eq!(wrapped(&vt), vec![true, true, true, false]);
+
+        // cursor above top margin
+
+        let mut vt = Vt::new(4, 4);
+
+        vt.feed_str("abcdefghijklmn");
+        vt.feed_str("\x1b[3;4r");
+        vt.feed_str("\x1b[1;1H");
+        vt.feed_str("\x1b[M");
+
+        assert_eq!(text(&vt), "|abcd\nefgh\nijkl\nmn");
+    }
+
+    #[test]
+    fn erase_at_pending_wrap() {
+        for seq in ["\x1b[K", "\x1b[X", "\x1b[J"] {
+            let mut vt = Vt::new(4, 2);
+
+            vt.feed_str("abcd");
+            vt.feed_str(seq);
+
+            assert_eq!(vt.line(0).text(), "abc ");
+
+            vt.feed_str("e");
+
+            assert_eq!(text(&vt), "abc\ne|");
+        }
+    }
+
+    #[test]
+    fn execute_el() {
+        // short lines
+
+        // a) clear to the end of the line
+
+        let mut vt = build_vt(4, 2, 2, 0, "abcd");
+
+        vt.feed_str("\x1b[0K");
+
+        assert_eq!(text(&vt), "ab|\n");
+
+        let mut vt = build_vt(4, 2, 2, 0, "a");
+
+        vt.feed_str("\x1b[0K");
+
+        assert_eq!(text(&vt), "a |\n");
+
+        // b) clear to the beginning of the line
+
+        let mut vt = build_vt(4, 2, 2, 0, "abcd");
+
+        vt.feed_str("\x1b[1K");
+
+        assert_eq!(text(&vt), "  | d\n");
+
+        // c) clear the whole line
+
+        let mut vt = build_vt(4, 2, 2, 0, "abcd");
+
+        vt.feed_str("\x1b[2K");
+
+        assert_eq!(text(&vt), "  |\n");
+
+        // wrapped lines
+
+        // a) clear to the end of the line
+
+        let mut vt = Vt::new(4, 3);
+
+        vt.feed_str("abcdefghij\x1b[A");
+        vt.feed_str("\x1b[0K");
+
+        assert_eq!(text(&vt), "abcd\nef|\nij");
+        assert_eq!(wrapped(&vt), vec![true, false, false]);
+
+        // b) clear to the beginning of the line
+
+        let mut vt = Vt::new(4, 3);
+
+        vt.feed_str("abcdefghij\x1b[A");
+        vt.feed_str("\x1b[1K");
+
+        assert_eq!(text(&vt), "abcd\n  | h\nij");
+        assert_eq!(wrapped(&vt), vec![true, true, false]);
+
+        // c) clear the whole line
+
+        let mut vt = Vt::new(4, 3);
+
+        vt.feed_str("abcdefghij\x1b[A");
+        vt.feed_str("\x1b[2K");
+
+        assert_eq!(text(&vt), "abcd\n  |\nij");
+        assert_eq!(wrapped(&vt), vec![true, false, false]);
+    }
+
+    #[test]
+    fn execute_ed() {
+        // short lines
+
+        // a) clear to the end of the screen
+
+        let mut vt = build_vt(4, 3, 1, 1, "abc\r\ndef\r\nghi");
+
+        vt.feed_str("\x1b[0J");
+
+        assert_eq!(text(&vt), "abc\nd|\n");
+
+        let mut vt = build_vt(4, 3, 1, 1, "abc\r\n\r\nghi");
+
+        vt.feed_str("\x1b[0J");
+
+        assert_eq!(text(&vt), "abc\n |\n");
+
+        // b) clear to the beginning of the screen
+
+        let mut vt = build_vt(4, 3, 1, 1, "abc\r\ndef\r\nghi");
+
+        vt.feed_str("\x1b[1J");
+
+        assert_eq!(text(&vt), "\n | f\nghi");
+
+        // c) clear the whole screen
+
+        let mut vt = build_vt(4, 3, 1, 1, "abc\r\ndef\r\nghi");
+
+        vt.feed_str("\x1b[2J");
+
+        assert_eq!(text(&vt), "\n |\n");
+
+        // wrapped lines
+
+        // a) clear to the end of the screen
+
+        let mut vt = build_vt(4, 3, 1, 1, "abcdefghij");
+
+        vt.feed_str("\x1b[0J");
+
+        assert_eq!(text(&vt), "abcd\ne|\n");
+        assert_eq!(wrapped(&vt), vec![true, false, false]);
+
+        // b) clear to the beginning of the screen
+
+        let mut vt = build_vt(4, 3, 1, 1, "abcdefghij");
+
+        vt.feed_str("\x1b[1J");
+
+        assert_eq!(text(&vt), "\n | gh\nij");
+        assert_eq!(wrapped(&vt), vec![false, true, false]);
+
+        // c) clear the whole screen
+
+        let mut vt = build_vt(4, 3, 1, 1, "abcdefghij");
+
+        vt.feed_str("\x1b[2J");
+
+        assert_eq!(text(&vt), "\n |\n");
+        assert_eq!(wrapped(&vt), vec![false, false, false]);
+    }
+
+    #[test]
+    fn execute_dch() {
+        let mut vt = build_vt(8, 2, 3, 0, "abcdefghijkl");
+
+        vt.feed_str("\x1b[P");
+
+        assert_eq!(text(&vt), "abc|efgh\nijkl");
+        assert_eq!(wrapped(&vt), vec![false, false]);
+
+        vt.feed_str("\x1b[2P");
+
+        assert_eq!(text(&vt), "abc|gh\nijkl");
+
+        vt.feed_str("\x1b[10P");
+
+        assert_eq!(text(&vt), "abc|\nijkl");
+
+        vt.feed_str("\x1b[10C");
+        vt.feed_str("\x1b[10P");
+
+        assert_eq!(text(&vt), "abc    |\nijkl");
+
+        // pending wrap after exact fill
+
+        let mut vt = Vt::new(8, 2);
+
+        vt.feed_str("abcdefgh\x1b[P");
+
+        assert_eq!(text(&vt), "abcdefg|\n");
+
+        vt.feed_str("x");
+
+        assert_eq!(text(&vt), "abcdefgx|\n");
+        assert_eq!(wrapped(&vt), vec![false, false]);
+    }
+
+    #[test]
+    fn execute_ech() {
+        let mut vt = build_vt(8, 2, 3, 0, "abcdefghijkl");
+
+        vt.feed_str("\x1b[X");
+
+        assert_eq!(text(&vt), "abc| efgh\nijkl");
+        assert_eq!(wrapped(&vt), vec![true, false]);
+
+        vt.feed_str("\x1b[2X");
+
+        assert_eq!(text(&vt), "abc|  fgh\nijkl");
+        assert_eq!(wrapped(&vt), vec![true, false]);
+
+        vt.feed_str("\x1b[10X");
+
+        assert_eq!(text(&vt), "abc|\nijkl");
+        assert_eq!(wrapped(&vt), vec![false, false]);
+
+        vt.feed_str("\x1b[3C\x1b[X");
+
+        assert_eq!(text(&vt), "abc   |\nijkl");
+
+        // erasing up to, but not including, the last column keeps the wrap
+
+        let mut vt = build_vt(8, 2, 3, 0, "abcdefghijkl");
+
+        vt.feed_str("\x1b[4X");
+
+        assert_eq!(text(&vt), "abc|    h\nijkl");
+        assert_eq!(wrapped(&vt), vec![true, false]);
+
+        // erasing exactly through the last column clears it
+
+        vt.feed_str("\x1b[5X");
+
+        assert_eq!(text(&vt), "abc|\nijkl");
+        assert_eq!(wrapped(&vt), vec![false, false]);
+    }
+
+    #[test]
+    fn execute_cht() {
+        let mut vt = build_vt(28, 1, 3, 0, "abcdefghijklmnopqrstuwxyzabc");
+
+        vt.feed_str("\x1b[I");
+
+        assert_eq!(vt.cursor(), (8, 0));
+
+        vt.feed_str("\x1b[2I");
+
+        assert_eq!(vt.cursor(), (24, 0));
+
+        vt.feed_str("\x1b[I");
+
+        assert_eq!(vt.cursor(), (27, 0));
+    }
+
+    #[test]
+    fn execute_cbt() {
+        let mut vt = build_vt(28, 1, 26, 0, "abcdefghijklmnopqrstuwxyzabc");
+
+        vt.feed_str("\x1b[Z");
+
+        assert_eq!(vt.cursor(), (24, 0));
+
+        vt.feed_str("\x1b[2Z");
+
+        assert_eq!(vt.cursor(), (8, 0));
+
+        vt.feed_str("\x1b[Z");
+
+        assert_eq!(vt.cursor(), (0, 0));
+    }
+
+    #[test]
+    fn cursor_cell() {
+        let mut vt = Vt::new(3, 2);
+
+        vt.feed_str("ab\x1b[1mc");
+
+        assert_eq!(vt.cursor(), (3, 0));
+        assert_eq!(vt.cursor_cell().unwrap().char(), 'c');
+        assert!(vt.cursor_cell().unwrap().pen().is_bold());
+
+        vt.feed_str("\x1b[2;2H");
+
+        assert_eq!(vt.cursor_cell().unwrap().char(), ' ');
+
+        vt.feed_str("\x1b[?25l");
+
+        assert!(vt.cursor_cell().is_none());
+    }
+
+    #[test]
+    fn execute_sc_rc() {
+        // DECSC/DECRC variant
+
+        let mut vt = build_vt(4, 3, 0, 0, "");
+
+        // move 2x right, 1 down
+        vt.feed_str("  \n");
+
+        // save cursor
+        vt.feed_str("\x1b7");
+
+        // move 1x right, 1x down
+        vt.feed_str(" \n");
+
+        // restore cursor
+        vt.feed_str("\x1b8");
+
+        assert_eq!(vt.cursor(), (2, 1));
+
+        // ansi.sys variant
+
+        let mut vt = build_vt(4, 3, 0, 0, "");
+
+        // move 2x right, 1 down
+        vt.feed_str("  \n");
+
+        // save cursor
+        vt.feed_str("\x1b[s");
+
+        // move 1x right, 1x down
+        vt.feed_str(" \n");
+
+        // restore cursor
+        vt.feed_str("\x1b[u");
+
+        assert_eq!(vt.cursor(), (2, 1));
+
+        // interleaved DEC and ansi.sys variants
+
+        let mut vt = build_vt(4, 3, 0, 0, "");
+
+        // save cursor at (1, 0) with DECSC
+        vt.feed_str(" \x1b7");
+
+        // save cursor at (2, 1) with SCOSC
+        vt.feed_str("\x1b[2;3H\x1b[s");
+
+        vt.feed_str("\x1b[3;4H\x1b8");
+
+        assert_eq!(vt.cursor(), (1, 0));
+
+        vt.feed_str("\x1b[u");
+
+        assert_eq!(vt.cursor(), (2, 1));
+
+        vt.feed_str("\x1b8");
+
+        assert_eq!(vt.cursor(), (1, 0));
+
+        // charsets are saved and restored
+
+        let mut vt = build_vt(4, 3, 0, 0, "");
+
+        vt.feed_str("\x1b(0\x1b7\x1b(Bq\x1b8q");
+
+        assert_eq!(text(&vt), "\u{2500}|\n\n");
+
+        vt.feed_str("\x1b)0\x0e\x1b[s\x1b)B\x0fq\x1b[uq");
+
+        assert_eq!(text(&vt), "\u{2500}\u{2500}|\n\n");
+    }
+
+    #[test]
+    fn execute_rep() {
+        let mut vt = build_vt(20, 2, 0, 0, "");
+
+        vt.feed_str("\x1b[b"); // REP
+
+        assert_eq!(text(&vt), "|\n");
+
+        vt.feed_str("A");
+        vt.feed_str("\x1b[b");
+
+        assert_eq!(text(&vt), "AA|\n");
+
+        vt.feed_str("\x1b[3b");
+
+        assert_eq!(text(&vt), "AAAAA|\n");
+
+        vt.feed_str("\x1b[5C"); // move 5 cols to the right
+        vt.feed_str("\x1b[b");
+
+        assert_eq!(text(&vt), "AAAAA      |\n");
+    }
+
+    #[test]
+    fn print_wrap_below_bottom_margin() {
+        let mut vt = Vt::new(4, 5);
+
+        vt.feed_str("top\x1b[1;2r\x1b[4;1Habcdef");
+
+        assert_eq!(text(&vt), "top\n\n\nabcd\nef|");
+        assert_eq!(wrapped(&vt), vec![false, false, false, true, false]);
+
+        // on the last row, outside the region: wrap in place, no scrolling
+
+        vt.feed_str("gh\x1b[5;3Hxyz");
+
+        assert_eq!(text(&vt), "top\n\n\nabcd\nz|fxy");
+        assert_eq!(wrapped(&vt), vec![false, false, false, true, false]);
+        assert_eq!(vt.scrollback_len(), 0);
+    }
+
+    #[test]
+    fn execute_rep_wrapping() {
+        let mut vt = Vt::new(10, 3);
+
+        vt.feed_str("abcdefgh\x1b[10b");
+
+        assert_eq!(text(&vt), "abcdefghhh\nhhhhhhhh|\n");
+        assert_eq!(wrapped(&vt), vec![true, false, false]);
+
+        // REP at pending wrap repeats the last column's char on the next line
+
+        let mut vt = Vt::new(10, 3);
+
+        vt.feed_str("abcdefghij");
+
+        assert_eq!(vt.cursor(), (10, 0));
+
+        vt.feed_str("\x1b[3b");
+
+        assert_eq!(text(&vt), "abcdefghij\njjj|\n");
+        assert_eq!(wrapped(&vt), vec![true, false, false]);
+
+        // REP exactly filling the line leaves the cursor at pending wrap
+
+        let mut vt = Vt::new(10, 3);
+
+        vt.feed_str("ab\x1b[8b");
+
+        assert_eq!(vt.cursor(), (10, 0));
+        assert_eq!(wrapped(&vt), vec![false, false, false]);
+
+        // auto-wrap off: REP overwrites the last column
+
+        let mut vt = Vt::new(10, 3);
+
+        vt.feed_str("\x1b[?7labcdefgh\x1b[10b");
+
+        assert_eq!(text(&vt), "abcdefghh|h\n\n");
+        assert_eq!(wrapped(&vt), vec![false, false, false]);
+    }
+
+    #[test]
+    fn execute_xtwinops_wider() {
+        let mut builder = Vt::builder();
+        builder.resizable(true);
+
+        let mut vt = builder.size(6, 6).build();
+
+        vt.feed_str("\x1b[8;6;7t");
+
+        assert_eq!(text(&vt), "|\n\n\n\n\n");
+        assert!(!vt.view().iter().any(|l| l.wrapped()));
+
+        vt.feed_str("\x1b[8;6;15t");
+
+        assert_eq!(text(&vt), "|\n\n\n\n\n");
+        assert!(!vt.view().iter().any(|l| l.wrapped()));
+
+        let mut vt = builder.size(6, 6).build();
+
+        vt.feed_str("000000111111222222333333444444555");
+
+        assert_eq!(text(&vt), "000000\n111111\n222222\n333333\n444444\n555|");
+        assert_eq!(wrapped(&vt), vec![true, true, true, true, true, false]);
+
+        vt.feed_str("\x1b[8;6;7t");
+
+        assert_eq!(text(&vt), "0000001\n1111122\n2222333\n3334444\n44555|\n");
+        assert_eq!(wrapped(&vt), vec![true, true, true, true, false, false]);
+
+        vt.feed_str("\x1b[8;6;15t");
+
+        assert_eq!(text(&vt), "000000111111222\n222333333444444\n555|\n\n\n");
+        assert_eq!(wrapped(&vt), vec![true, true, false, false, false, false]);
+
+        let mut vt = builder.size(4, 3).build();
+
+        vt.feed_str("000011\r\n22");
+
+        assert_eq!(text(&vt), "0000\n11\n22|");
+        assert_eq!(wrapped(&vt), vec![true, false, false]);
+
+        vt.feed_str("\x1b[8;3;8t");
+
+        assert_eq!(text(&vt), "000011\n22|\n");
+        assert_eq!(wrapped(&vt), vec![false, false, false]);
+    }
+
+    #[test]
+    fn execute_xtwinops_narrower() {
+        let mut builder = Vt::builder();
+        builder.resizable(true);
+
+        let mut vt = builder.size(15, 6).build();
+
+        vt.feed_str("\x1b[8;6;7t");
+
+        assert_eq!(text(&vt), "|\n\n\n\n\n");
+        assert!(!vt.view().iter().any(|l| l.wrapped()));
+
+        vt.feed_str("\x1b[8;6;6t");
+
+        assert_eq!(text(&vt), "|\n\n\n\n\n");
+        assert!(!vt.view().iter().any(|l| l.wrapped()));
+
+        let mut vt = builder.size(8, 2).build();
+
+        vt.feed_str("\nabcdef");
+
+        assert_eq!(wrapped(&vt), vec![false, false]);
+
+        vt.feed_str("\x1b[8;;4t");
+
+        assert_eq!(text(&vt), "abcd\nef|");
+        assert_eq!(wrapped(&vt), vec![true, false]);
+
+        let mut vt = builder.size(15, 6).build();
+
+        vt.feed_str("000000111111222222333333444444555");
+
+        assert_eq!(text(&vt), "000000111111222\n222333333444444\n555|\n\n\n");
+        assert_eq!(wrapped(&vt), vec![true, true, false, false, false, false]);
+
+        vt.feed_str("\x1b[8;6;7t");
+
+        assert_eq!(text(&vt), "2222333\n3334444\n44555|\n\n\n");
+        assert_eq!(wrapped(&vt), vec![true, true, false, false, false, false]);
+
+        vt.feed_str("\x1b[8;6;6t");
+
+        assert_eq!(text(&vt), "333333\n444444\n555|\n\n\n");
+        assert_eq!(wrapped(&vt), vec![true, true, false, false, false, false]);
+    }
+
+    #[test]
+    fn execute_xtwinops() {
+        let mut vt = Vt::builder().size(8, 4).resizable(true).build();
+        vt.feed_str("abcdefgh\r\nijklmnop\r\nqrstuw");
+        vt.feed_str("\x1b[4;1H");
+
+        let resized = vt.feed_str("AAA").resized;
+
+        assert!(!resized);
+
+        let resized = vt.feed_str("\x1b[8;5;t").resized;
 
-        let input = "\x1b[;3rxxxxxxxxxx\x1b[50;1Hyyy\x1b[50Czzz";
+        assert!(resized);
+        assert_eq!(text(&vt), "abcdefgh\nijklmnop\nqrstuw\nAAA|\n");
 
-        vt.feed_str(input);
+        vt.feed_str("BBBBB");
 
-        assert_eq!(text(&vt), "xxxx\nxxxx\nxx\n\n\nzz|yz");
-    }
+        assert_eq!(vt.cursor(), (8, 3));
 
-    #[test]
-    fn execute_lf() {
-        let mut vt = build_vt(8, 2, 3, 0, "abc");
+        let resized = vt.feed_str("\x1b[8;;4t").resized;
 
-        vt.feed_str("\n");
+        assert!(resized);
+        assert_eq!(text(&vt), "qrst\nuw\nAAAB\nBBB|B\n");
 
-        assert_eq!(vt.cursor(), (3, 1));
-        assert_eq!(text(&vt), "abc\n   |");
+        vt.feed_str("\rCCC");
 
-        vt.feed_str("d\n");
+        assert_eq!(text(&vt), "qrst\nuw\nAAAB\nCCC|B\n");
+        assert_eq!(wrapped(&vt), vec![true, false, true, false, false]);
 
-        assert_eq!(vt.cursor(), (4, 1));
-        assert_eq!(text(&vt), "   d\n    |");
-    }
+        vt.feed_str("\x1b[8;;3t");
 
-    #[test]
-    fn execute_ri() {
-        let mut vt = build_vt(8, 5, 0, 0, "abcd\r\nefgh\r\nijkl\r\nmnop\r\nqrst");
+        assert_eq!(text(&vt), "tuw\nAAA\nBCC\nC|B\n");
 
-        vt.feed_str("\x1bM"); // RI
+        vt.feed_str("\x1b[8;;5t");
 
-        assert_eq!(text(&vt), "|\nabcd\nefgh\nijkl\nmnop");
+        assert_eq!(text(&vt), "qrstu\nw\nAAABC\nCC|B\n");
 
-        vt.feed_str("\x1b[3;4r"); // use smaller scroll region
-        vt.feed_str("\x1b[3;1H"); // place cursor on top margin
-        vt.feed_str("\x1bM"); // RI
+        vt.feed_str("DDD");
+        vt.feed_str("\x1b[8;;6t");
 
-        assert_eq!(text(&vt), "\nabcd\n|\nefgh\nmnop");
+        assert_eq!(text(&vt), "op\nqrstuw\nAAABCC\nCDDD|\n");
     }
 
     #[test]
-    fn execute_su() {
-        // short lines, default margins
+    fn execute_xtwinops_noop() {
+        let mut vt = Vt::new(8, 4);
 
-        let mut vt = Vt::new(4, 6);
-        vt.feed_str("aa\r\nbb\r\ncc\r\ndd\r\nee\r\nff");
-        vt.feed_str("\x1b[2S");
-        assert_eq!(text(&vt), "cc\ndd\nee\nff\n\n  |");
+        let resized = vt.feed_str("\x1b[8;;t").resized;
 
-        // short lines, margins at 1 (top) and 4 (bottom)
+        assert!(!resized);
+    }
 
-        let mut vt = Vt::new(4, 6);
+    #[test]
+    fn execute_xtwinops_taller() {
+        let mut vt = Vt::builder().size(6, 4).resizable(true).build();
 
-        vt.feed_str("aa\r\nbb\r\ncc\r\ndd\r\nee\r\nff");
-        vt.feed_str("\x1b[2;5r");
-        vt.feed_str("\x1b[1;1H");
-        vt.feed_str("\x1b[2S");
+        vt.feed_str("AAA\n\rBBB\n\r");
+        let resized = vt.feed_str("\x1b[8;5;;t").resized;
 
-        assert_eq!(text(&vt), "|aa\ndd\nee\n\n\nff");
+        assert!(resized);
+        assert_eq!(text(&vt), "AAA\nBBB\n|\n\n");
+    }
 
-        // wrapped lines, default margins
+    #[test]
+    fn execute_xtwinops_shorter() {
+        let mut vt = Vt::builder().size(6, 6).resizable(true).build();
 
-        let mut vt = Vt::new(4, 6);
+        vt.feed_str("AAA\n\rBBB\n\rCCC\n\r");
 
-        vt.feed_str("aaaaaa\r\nbbbbbb\r\ncccccc");
-        vt.feed_str("\x1b[2S");
+        let resized = vt.feed_str("\x1b[8;5;;t").resized;
 
-        assert_eq!(text(&vt), "bbbb\nbb\ncccc\ncc\n\n  |");
-        assert_eq!(wrapped(&vt), vec![true, false, true, false, false, false]);
+        assert!(resized);
+        assert_eq!(text(&vt), "AAA\nBBB\nCCC\n|\n");
 
-        // wrapped lines, margins at 1 (top) and 4 (bottom)
+        let resized = vt.feed_str("\x1b[8;3;;t").resized;
 
-        let mut vt = Vt::new(4, 6);
+        assert!(resized);
+        assert_eq!(text(&vt), "BBB\nCCC\n|");
 
-        vt.feed_str("aaaaaa\r\nbbbbbb\r\ncccccc");
-        vt.feed_str("\x1b[2;5r");
-        vt.feed_str("\x1b[1;1H");
-        vt.feed_str("\x1b[2S");
+        let resized = vt.feed_str("\x1b[8;2;;t").resized;
 
-        assert_eq!(text(&vt), "|aaaa\nbb\ncccc\n\n\ncc");
-        assert_eq!(wrapped(&vt), vec![false, false, false, false, false, false]);
+        assert!(resized);
+        assert_eq!(text(&vt), "CCC\n|");
     }
 
     #[test]
-    fn execute_sd() {
-        // short lines, default margins
+    fn execute_xtwinops_vs_buffer_switching() {
+        let mut vt = Vt::builder().size(4, 4).resizable(true).build();
 
-        let mut vt = Vt::new(4, 6);
+        // fill primary buffer
+        vt.feed_str("aaa\n\rbbb\n\rc\n\rddd");
 
-        vt.feed_str("aa\r\nbb\r\ncc\r\ndd\r\nee\r\nff");
-        vt.feed_str("\x1b[2T");
+        assert_eq!(vt.cursor(), (3, 3));
 
-        assert_eq!(text(&vt), "\n\naa\nbb\ncc\ndd|");
+        // resize to 4x5
+        vt.feed_str("\x1b[8;5;4;t");
 
-        // short lines, margins at 1 (top) and 4 (bottom)
+        assert_eq!(text(&vt), "aaa\nbbb\nc\nddd|\n");
 
-        let mut vt = Vt::new(4, 6);
+        // switch to alternate buffer
+        vt.feed_str("\x1b[?1049h");
 
-        vt.feed_str("aa\r\nbb\r\ncc\r\ndd\r\nee\r\nff");
-        vt.feed_str("\x1b[2;5r");
-        vt.feed_str("\x1b[1;1H");
-        vt.feed_str("\x1b[2T");
+        assert_eq!(vt.cursor(), (3, 3));
 
-        assert_eq!(text(&vt), "|aa\n\n\nbb\ncc\nff");
+        // resize to 4x2
+        vt.feed_str("\x1b[8;2;4t");
 
-        // wrapped lines, default margins
+        assert_eq!(vt.cursor(), (3, 1));
 
-        let mut vt = Vt::new(4, 6);
+        // resize to 2x3, we'll check later if primary buffer preserved more columns
+        vt.feed_str("\x1b[8;3;2t");
 
-        vt.feed_str("aaaaaa\r\nbbbbbb\r\ncccccc");
-        vt.feed_str("\x1b[2T");
+        // resize to 3x3
+        vt.feed_str("\x1b[8;3;3t");
 
-        assert_eq!(text(&vt), "\n\naaaa\naa\nbbbb\nbb|");
-        assert_eq!(wrapped(&vt), vec![false, false, true, false, true, false]);
+        // switch back to primary buffer
+        vt.feed_str("\x1b[?1049l");
 
-        // wrapped lines, margins at 1 (top) and 4 (bottom)
+        assert_eq!(text(&vt), "bbb\nc\ndd|d");
+    }
 
-        let mut vt = Vt::new(4, 6);
+    #[test]
+    fn scrollback() {
+        let mut vt = Vt::builder().size(4, 2).scrollback_limit(10).build();
 
-        vt.feed_str("aaaaaa\r\nbbbbbb\r\ncccccc");
-        vt.feed_str("\x1b[2;5r");
-        vt.feed_str("\x1b[1;1H");
-        vt.feed_str("\x1b[2T");
+        let lines: Vec<String> = vt
+            .feed_str("a\r\nb\r\nc\r\nd\r\ne\r\nf\r\ng\r\nh\r\ni\r\nj\r\nk\r\nl\r\n")
+            .scrollback
+            .map(|l| l.text())
+            .collect();
 
-        assert_eq!(text(&vt), "|aaaa\n\n\naa\nbbbb\ncc");
-        assert_eq!(wrapped(&vt), vec![false, false, false, false, false, false]);
+        assert!(lines.is_empty());
+
+        let lines: Vec<String> = vt.feed_str("m\r\n").scrollback.map(|l| l.text()).collect();
+
+        assert_eq!(lines, vec!["a   ", "b   "]);
     }
 
     #[test]
-    fn execute_bs() {
-        let mut vt = Vt::new(4, 2);
+    fn scrollback_len() {
+        let mut vt = Vt::builder().size(4, 3).scrollback_limit(3).build();
 
-        vt.feed_str("a");
-        vt.feed_str("\x08");
+        assert_eq!(vt.scrollback_len(), 0);
+        assert_eq!(vt.feed_str("a\r\nb\r\nc\r\nd").scrolled, 1);
+        assert_eq!(vt.scrollback_len(), 1);
 
-        assert_eq!(text(&vt), "|a\n");
+        let Changes {
+            scrolled,
+            scrollback,
+            ..
+        } = vt.feed_str("\r\ne\r\nf\r\ng\r\nh");
 
-        vt.feed_str("\x08");
+        assert_eq!(scrolled, 4);
+        assert_eq!(scrollback.count(), 2);
+        assert_eq!(vt.scrollback_len(), 3);
 
-        assert_eq!(text(&vt), "|a\n");
+        // scrolling a region not touching the top doesn't feed scrollback
+        assert_eq!(vt.feed_str("\x1b[2;3r\x1b[3;1H\n\n").scrolled, 0);
+        assert_eq!(vt.scrollback_len(), 3);
 
-        vt.feed_str("abcd");
-        vt.feed_str("\x08");
+        // alternate screen doesn't feed scrollback
+        assert_eq!(vt.feed_str("\x1b[r\x1b[?1049h\n\n\n").scrolled, 0);
+        assert_eq!(vt.scrollback_len(), 3);
 
-        assert_eq!(text(&vt), "ab|cd\n");
+        // deleting lines at the top feeds scrollback
+        assert_eq!(vt.feed_str("\x1b[?1049l\x1b[H\x1b[2M").scrolled, 2);
+    }
 
-        vt.feed_str("cdef");
-        vt.feed_str("\x08");
+    #[test]
+    fn builder_alternate_screen() {
+        let mut vt = Vt::builder().size(4, 2).alternate_screen(true).build();
 
-        assert_eq!(text(&vt), "abcd\ne|f");
+        assert!(vt.is_alternate_buffer());
+        assert!(vt.feed_str("ab").buffer_transitions.is_empty());
+        assert!(vt.dump().contains("\u{9b}?1047h"));
 
-        vt.feed_str("\x08");
+        let mut vt2 = Vt::new(4, 2);
+        vt2.feed_str(&vt.dump());
 
-        assert_eq!(text(&vt), "abcd\n|ef");
+        assert!(vt2.is_alternate_buffer());
+        assert_vts_eq(&vt, &vt2);
 
-        vt.feed_str("\x08");
+        vt.feed_str("\x1b[?1047l");
 
-        assert_eq!(text(&vt), "abcd\n|ef");
+        assert!(!vt.is_alternate_buffer());
+        assert_eq!(text(&vt), "  |\n");
     }
 
     #[test]
-    fn execute_cup() {
-        let mut vt = Vt::new(4, 2);
+    fn screen_range() {
+        let mut vt = Vt::builder().size(4, 2).scrollback_limit(10).build();
 
-        vt.feed_str("abc\r\ndef");
-        vt.feed_str("\x1b[1;1;H");
+        assert_eq!(vt.screen_range(), 0..2);
 
-        assert_eq!(vt.cursor(), (0, 0));
+        vt.feed_str("a\r\nb\r\nc\r\nd");
 
-        vt.feed_str("\x1b[10;10;H");
+        assert_eq!(vt.screen_range(), 2..4);
+        assert_eq!(vt.lines()[vt.screen_range()], *vt.view());
 
-        assert_eq!(vt.cursor(), (3, 1));
+        vt.feed_str("\x1b[?1049h");
+
+        assert_eq!(vt.screen_range(), 0..2);
+        assert_eq!(vt.lines()[vt.screen_range()], *vt.view());
     }
 
     #[test]
-    fn execute_cuu() {
-        let mut vt = Vt::new(8, 4);
-
-        vt.feed_str("abcd\n\n\n");
-        vt.feed_str("\x1b[A");
+    fn execute_ed_saved_lines() {
+        let mut vt = Vt::builder().size(4, 2).build();
 
-        assert_eq!(vt.cursor(), (4, 2));
+        vt.feed_str("a\r\nb\r\nc\r\nd");
 
-        vt.feed_str("\x1b[2A");
+        assert_eq!(vt.scrollback_len(), 2);
 
-        assert_eq!(vt.cursor(), (4, 0));
-    }
+        let scrollback: Vec<String> = vt
+            .feed_str("\x1b[3J")
+            .scrollback
+            .map(|l| l.text())
+            .collect();
 
-    #[test]
-    fn execute_cpl() {
-        let mut vt = Vt::new(8, 4);
+        assert_eq!(scrollback, vec!["a   ", "b   "]);
+        assert_eq!(vt.scrollback_len(), 0);
+        assert_eq!(text(&vt), "c\nd|");
 
-        vt.feed_str("abcd\r\n\r\n\r\nef");
+        // lines scrolled in after ED 3 are kept
 
-        assert_eq!(vt.cursor(), (2, 3));
+        let scrollback: Vec<String> = vt
+            .feed_str("\x1b[3J\r\ne\r\nf")
+            .scrollback
+            .map(|l| l.text())
+            .collect();
 
-        vt.feed_str("\x1b[F");
+        assert!(scrollback.is_empty());
+        assert_eq!(vt.scrollback_len(), 2);
+        assert_eq!(text(&vt), "e\nf|");
 
-        assert_eq!(vt.cursor(), (0, 2));
+        // on the alternate screen it clears the primary scrollback
 
-        vt.feed_str("\x1b[2F");
+        let scrollback: Vec<String> = vt
+            .feed_str("\x1b[?1049h\x1b[3J")
+            .scrollback
+            .map(|l| l.text())
+            .collect();
 
-        assert_eq!(vt.cursor(), (0, 0));
+        assert_eq!(scrollback, vec!["c   ", "d   "]);
+        assert_eq!(vt.scrollback_len(), 0);
     }
 
     #[test]
-    fn execute_cnl() {
-        let mut vt = Vt::new(4, 4);
-
-        vt.feed_str("ab");
-        vt.feed_str("\x1b[E");
+    fn scrollback_without_slack() {
+        let mut vt = Vt::builder()
+            .size(4, 2)
+            .scrollback_limit(10)
+            .scrollback_slack(false)
+            .build();
+
+        let lines: Vec<String> = vt
+            .feed_str("a\r\nb\r\nc\r\nd\r\ne\r\nf\r\ng\r\nh\r\ni\r\nj\r\nk\r\nl\r\n")
+            .scrollback
+            .map(|l| l.text())
+            .collect();
+
+        assert_eq!(lines, vec!["a   "]);
+
+        let lines: Vec<String> = vt
+            .feed_str("m\r\nn\r\n")
+            .scrollback
+            .map(|l| l.text())
+            .collect();
+
+        assert_eq!(lines, vec!["b   ", "c   "]);
+    }
 
-        assert_eq!(vt.cursor(), (0, 1));
+    #[test]
+    fn screen_text() {
+        let mut vt = Vt::builder().size(4, 3).scrollback_limit(0).build();
 
-        vt.feed_str("\x1b[3E");
+        vt.feed_str("abcdef  \r\n  g\r\nh");
 
-        assert_eq!(vt.cursor(), (0, 3));
+        assert_eq!(vt.screen_text(), "ef\n  g\nh");
     }
 
     #[test]
-    fn execute_vpa() {
-        let mut vt = Vt::new(4, 4);
+    fn screen_hash() {
+        let mut vt1 = Vt::new(4, 2);
+        let mut vt2 = Vt::builder().size(4, 2).scrollback_limit(0).build();
 
-        vt.feed_str("\r\n\r\naaa\r\nbbb");
-        vt.feed_str("\x1b[d");
+        assert_eq!(vt1.screen_hash(false), 0x9fab23341c9aaf8d);
 
-        assert_eq!(vt.cursor(), (3, 0));
+        vt1.feed_str("ab\r\ncd\r\nef");
+        vt2.feed_str("xx\r\ncd\r\nef");
 
-        vt.feed_str("\x1b[10d");
+        assert_eq!(vt1.screen_hash(false), vt2.screen_hash(false));
+        assert_eq!(vt1.screen_hash(true), vt2.screen_hash(true));
 
-        assert_eq!(vt.cursor(), (3, 3));
+        vt2.feed_str("\x1b[H");
+
+        assert_eq!(vt1.screen_hash(false), vt2.screen_hash(false));
+        assert_ne!(vt1.screen_hash(true), vt2.screen_hash(true));
+
+        vt2.feed_str("\x1b[1mc");
+
+        assert_ne!(vt1.screen_hash(false), vt2.screen_hash(false));
     }
 
     #[test]
-    fn execute_cud() {
-        let mut vt = Vt::new(8, 4);
+    fn visible_string() {
+        let mut vt = Vt::new(4, 4);
 
-        vt.feed_str("abcd");
-        vt.feed_str("\x1b[B");
+        vt.feed_str("ab\r\n  c\r\n");
 
-        assert_eq!(text(&vt), "abcd\n    |\n\n");
+        assert_eq!(vt.visible_string(), "ab  \n  c");
 
-        vt.feed_str("\x1b[2B");
+        vt.feed_str("\x1b[4;2Hd");
 
-        assert_eq!(text(&vt), "abcd\n\n\n    |");
+        assert_eq!(vt.visible_string(), "ab  \n  c \n    \n d");
     }
 
     #[test]
-    fn execute_cuf() {
-        let mut vt = Vt::new(4, 1);
-
-        vt.feed_str("\x1b[2C");
+    fn char_at() {
+        let mut vt = Vt::new(4, 2);
 
-        assert_eq!(text(&vt), "  |");
+        vt.feed_str("ab\r\n\u{3042}x");
 
-        vt.feed_str("\x1b[2C");
+        assert_eq!(vt.char_at(1, 0), Some('b'));
+        assert_eq!(vt.char_at(3, 0), Some(' '));
+        assert_eq!(vt.char_at(0, 1), Some('\u{3042}'));
+        assert_eq!(vt.char_at(1, 1), Some('x'));
+        assert_eq!(vt.char_at(4, 0), None);
+        assert_eq!(vt.char_at(0, 2), None);
+    }
 
-        assert_eq!(text(&vt), "   |");
+    #[test]
+    fn cursor_position() {
+        let mut vt = Vt::new(8, 4);
 
-        vt.feed_str("a");
+        vt.feed_str("ab\r\nc");
 
-        assert_eq!(text(&vt), "   a|");
+        assert_eq!(vt.cursor_position(), Some((1, 1)));
 
-        vt.feed_str("\x1b[5C");
+        vt.feed_str("\x1b[?25l");
 
-        assert_eq!(text(&vt), "   |a");
+        assert!(!vt.cursor().visible);
+        assert_eq!(vt.cursor_position(), None);
 
-        vt.feed_str("ab");
-        vt.feed_str("\x1b[10C");
+        vt.feed_str("\x1b[?25h");
 
-        assert_eq!(text(&vt), "b  |");
+        assert_eq!(vt.cursor_position(), Some((1, 1)));
     }
 
     #[test]
-    fn execute_cub() {
-        let mut vt = Vt::new(8, 2);
-
-        vt.feed_str("abcd");
-        vt.feed_str("\x1b[2D");
-
-        assert_eq!(text(&vt), "ab|cd\n");
+    fn zero_size() {
+        let mut vt = Vt::builder().size(0, 0).resizable(true).build();
 
-        vt.feed_str("cdef");
-        vt.feed_str("\x1b[2D");
+        assert_eq!(vt.size(), (1, 1));
 
-        assert_eq!(text(&vt), "abcd|ef\n");
+        vt.feed_str("abc\r\ndef");
 
-        vt.feed_str("\x1b[10D");
+        assert_eq!(text(&vt), "f|");
 
-        assert_eq!(text(&vt), "|abcdef\n");
+        let resized = vt.feed_str("\x1b[8;0;0t").resized;
 
-        let mut vt = Vt::new(4, 2);
+        assert!(!resized);
+        assert_eq!(vt.size(), (1, 1));
 
-        vt.feed_str("abcd");
-        vt.feed_str("\x1b[D");
+        vt.feed_str("\x1b[8;3;4t");
 
-        assert_eq!(text(&vt), "ab|cd\n");
+        assert_eq!(vt.size(), (4, 3));
     }
 
     #[test]
-    fn execute_ich() {
-        let mut vt = build_vt(8, 2, 3, 0, "abcdefghijklmn");
+    fn to_ansi() {
+        let mut vt = Vt::new(6, 3);
 
-        vt.feed_str("\x1b[@");
+        vt.feed_str("ab\x1b[1;31mcd\x1b[0mefgh\r\n\x1b[7m  \x1b[0m  ");
 
-        assert_eq!(text(&vt), "abc| defg\nijklmn");
-        assert_eq!(wrapped(&vt), vec![true, false]);
+        assert_eq!(
+            vt.to_ansi(),
+            "\x1b[0mab\x1b[31;1mcd\x1b[0mef\x1b[0m\
+             \x1b[0mgh\x1b[0m\r\n\
+             \x1b[0;7m  \x1b[0m"
+        );
+    }
 
-        vt.feed_str("\x1b[2@");
+    #[test]
+    fn dump_line() {
+        let mut vt1 = Vt::new(4, 3);
+        let mut vt2 = Vt::new(4, 3);
 
-        assert_eq!(text(&vt), "abc|   de\nijklmn");
+        let lines = vt1.feed_str("ab\x1b[31mcdef\x1b[0m\r\n\x1b[1mgh").lines;
 
-        vt.feed_str("\x1b[10@");
+        for n in lines {
+            vt2.feed_str(&vt1.dump_line(n).unwrap());
+        }
 
-        assert_eq!(text(&vt), "abc|\nijklmn");
+        assert_eq!(vt2.view(), vt1.view());
+        assert_eq!(wrapped(&vt2), vec![true, false, false]);
+        assert_eq!(
+            vt1.dump_line(2).as_deref(),
+            Some("\x1b[3;1H\x1b[0;1mgh\x1b[0m  \x1b[0m")
+        );
+        assert_eq!(vt1.dump_line(3), None);
 
-        let mut vt = build_vt(8, 2, 7, 0, "abcdefghijklmn");
+        // wrapped row followed by the last row, after scrolling
+        let mut vt = Vt::new(4, 2);
+        vt.feed_str("abcdefghij");
 
-        vt.feed_str("\x1b[10@");
-        assert_eq!(text(&vt), "abcdefg|\nijklmn");
+        assert_eq!(wrapped(&vt), vec![true, false]);
+        assert_eq!(
+            vt.dump_line(0).as_deref(),
+            Some("\x1b[1;1H\x1b[0mefgh\x1b[0mi\x1b[0m")
+        );
+        assert_eq!(
+            vt.dump_line(1).as_deref(),
+            Some("\x1b[2;1H\x1b[0mij  \x1b[0m")
+        );
+        assert_eq!(vt.dump_line(2), None);
     }
 
     #[test]
-    fn execute_il() {
-        let mut vt = build_vt(4, 4, 2, 1, "abcdefghij");
+    fn dump_initial() {
+        let vt1 = Vt::new(10, 4);
+        let mut vt2 = Vt::new(10, 4);
 
-        vt.feed_str("\x1b[L");
+        vt2.feed_str(&vt1.dump());
 
-        assert_eq!(text(&vt), "abcd\n  |\nefgh\nij");
-        assert_eq!(wrapped(&vt), vec![false, false, true, false]);
+        assert_vts_eq(&vt1, &vt2);
+    }
 
-        vt.feed_str("\x1b[A");
-        vt.feed_str("\x1b[L");
+    #[test]
+    fn dump_modified() {
+        let mut vt1 = Vt::new(10, 4);
+        let mut vt2 = Vt::new(10, 4);
 
-        assert_eq!(text(&vt), "  |\nabcd\n\nefgh");
-        assert_eq!(wrapped(&vt), vec![false, false, false, false]);
+        vt1.feed_str("hello\n\rworld\u{9b}5W\u{9b}7`\u{1b}[W\u{9b}?6h");
+        vt1.feed_str("\u{9b}2;4r\u{9b}1;5H\x1b[1;31;41m\u{9b}?25l\u{9b}4h");
+        vt1.feed_str("\u{9b}?7l\u{9b}20h\u{9b}\u{3a}\x1b(0\x1b)0\u{0e}");
 
-        vt.feed_str("\x1b[3B");
-        vt.feed_str("\x1b[100L");
+        vt2.feed_str(&vt1.dump());
 
-        assert_eq!(text(&vt), "\nabcd\n\n  |");
+        assert_vts_eq(&vt1, &vt2);
     }
 
     #[test]
-    fn execute_dl() {
-        let mut vt = Vt::new(4, 4);
-
-        vt.feed_str("abcdefghijklmn");
-        vt.feed_str("\x1b[2A");
-        vt.feed_str("\x1b[M");
+    fn dump_alternate_screen_matrix() {
+        let cases = [
+            // primary only
+            "hello\n\rworld",
+            // primary with non-default saved ctx
+            "hello\x1b[2;3H\x1b[1;32m\x1b7\x1b[0m\x1b[4;1H",
+            // alt active, primary saved ctx default
+            "hello\x1b[?1049hfoo",
+            // alt active, primary saved ctx non-default
+            "hello\x1b[3;5H\x1b[3;34m\x1b[?1049h\x1b[0mfoo\x1b[2;2H",
+            // alt active, alt saved ctx non-default
+            "hello\x1b[?1047h\x1b[2;4H\x1b[7m\x1b7\x1b[0m\x1b[1;1Hbar",
+            // alt active, both saved ctxs non-default
+            "\x1b[2;2H\x1b[1m\x1b7\x1b[?1047h\x1b[3;3H\x1b[4m\x1b7\x1b[0mbaz",
+            // alt active with origin mode and no auto-wrap in saved ctxs
+            "\x1b[2;3r\x1b[?6h\x1b[?7l\x1b7\x1b[?1047h\x1b7\x1b[?6l\x1b[?7hqux",
+            // alt visited and left, alt saved ctx non-default
+            "\x1b[?1047h\x1b[2;4H\x1b7xyz\x1b[?1047lhello",
+            // alt visited and left via 1049
+            "hello\x1b[1;31m\x1b[?1049h\x1b[0mfoo\x1b[?1049l",
+            // cursor outside margins in origin mode, restored from DECSC
+            "\x1b[?6h\x1b[5;1H\x1b[?7l\x1b(0\x1b7\x1b[?7h\x1b(B\x1b[1;3r\x1b8\x1b[2C",
+            // ... restored from SCOSC, DECSC slot holding another ctx
+            "\x1b[?6h\x1b[s\x1b[1;2r\x1b[u\x1b[4B\x1b[2;1H\x1b7\x1b[u",
+            // ... restored from alternate screen's DECSC slot
+            "\x1b[?6h\x1b[3;1H\x1b[?1047h\x1b7\x1b[?1047l\x1b[1;2r\x1b[?1047h\x1b8\x1b[?1047l",
+            // drawing charsets in saved ctxs
+            "\x1b(0\x1b7\x1b(B\x1b)0\x0e\x1b[s\x0fqqq\x1b[?1047h\x1b)0\x0e\x1b7x",
+        ];
 
-        assert_eq!(text(&vt), "abcd\nij|kl\nmn\n");
-        assert_eq!(wrapped(&vt), vec![false, true, false, false]);
+        for input in cases {
+            let mut vt1 = Vt::new(10, 4);
+            let mut vt2 = Vt::new(10, 4);
 
-        // cursor above bottom margin
+            vt1.feed_str(input);
+            vt2.feed_str(&vt1.dump());
 
-        let mut vt = Vt::new(4, 4);
+            assert_vts_eq(&vt1, &vt2);
+        }
+    }
 
-        vt.feed_str("abcdefghijklmn");
-        vt.feed_str("\x1b[1;3r");
-        vt.feed_str("\x1b[2;1H");
-        vt.feed_str("\x1b[M");
+    #[cfg(feature = "binary")]
+    #[test]
+    fn bytes_round_trip() {
+        let inputs = [
+            "",
+            "hello\n\rworld\x1b[2;3r\x1b[1;31m\x1b[?25l\x1b[4h",
+            "\x1b[38;2;1;2;3mfoo\x1b[48;5;200mbar\x1b[0;4;9mbazqux",
+            "\x1b[2;2H\x1b[7m\x1b7\x1b[?1049h\x1b[3mfoo\x1b[?6h\x1b[1;1Hx",
+            "e\u{301}\u{1f468}\u{200d}\u{1f469}x\x1b[1;31mz\u{308}",
+            "ab\u{4f60}\u{597d}\x1b(0qqq\x1b(B\x1b[5;10Hz\x1b[",
+        ];
 
-        assert_eq!(text(&vt), "abcd\n|ijkl\n\nmn");
-        assert_eq!(wrapped(&vt), vec![false, false, false, false]);
+        for input in inputs {
+            let mut vt1 = Vt::new(10, 5);
+            vt1.feed_str(input);
 
-        // cursor below bottom margin
+            let bytes = vt1.to_bytes();
+            let vt2 = Vt::from_bytes(&bytes).unwrap();
 
-        let mut vt = Vt::new(4, 4);
+            assert_vts_eq(&vt1, &vt2);
+            assert!(bytes.len() < vt1.dump().len());
+        }
 
-        vt.feed_str("abcdefghijklmn");
-        vt.feed_str("\x1b[1;2r");
-        vt.feed_str("\x1b[4;1H");
-        vt.feed_str("\x1b[M");
+        let bytes = Vt::new(10, 5).to_bytes();
 
-        assert_eq!(text(&vt), "abcd\nefgh\nijkl\n|");
-        assert_eq!(wrapped(&vt), vec![true, true, false, false]);
+        assert!(Vt::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+        assert!(Vt::from_bytes(&bytes[1..]).is_none());
     }
 
+    #[cfg(feature = "binary")]
     #[test]
-    fn execute_el() {
-        // short lines
-
-        // a) clear to the end of the line
-
-        let mut vt = build_vt(4, 2, 2, 0, "abcd");
+    fn bytes_bad_header() {
+        let bytes = Vt::new(10, 5).to_bytes();
 
-        vt.feed_str("\x1b[0K");
+        for len in 0..8 {
+            assert!(Vt::from_bytes(&bytes[..len.min(bytes.len())]).is_none());
+        }
 
-        assert_eq!(text(&vt), "ab|\n");
+        // cols = 2^60, rows = 1
+        let mut bytes = b"AVT\x01".to_vec();
+        bytes.extend_from_slice(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x10, 1]);
+        bytes.extend_from_slice(&[
+            0, 0, 0, 1, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x10, 0, 32, 0,
+        ]);
 
-        let mut vt = build_vt(4, 2, 2, 0, "a");
+        assert!(Vt::from_bytes(&bytes).is_none());
 
-        vt.feed_str("\x1b[0K");
+        // rows exceeding what the remaining input could encode
+        let mut bytes = b"AVT\x01".to_vec();
+        bytes.extend_from_slice(&[10, 0xff, 0xff, 0x03, 0]);
 
-        assert_eq!(text(&vt), "a |\n");
+        assert!(Vt::from_bytes(&bytes).is_none());
 
-        // b) clear to the beginning of the line
+        // 65535x1000 blank cells encoded in ~8KB
+        let mut bytes = b"AVT\x01".to_vec();
+        bytes.extend_from_slice(&[0xff, 0xff, 0x03, 0xe8, 0x07, 0, 0]);
 
-        let mut vt = build_vt(4, 2, 2, 0, "abcd");
+        for _ in 0..1000 {
+            bytes.extend_from_slice(&[0, 1, 0xff, 0xff, 0x03, 0, 32, 0]);
+        }
 
-        vt.feed_str("\x1b[1K");
+        assert!(Vt::from_bytes(&bytes).is_none());
+    }
 
-        assert_eq!(text(&vt), "  | d\n");
+    #[cfg(feature = "binary")]
+    #[test]
+    fn bytes_width_mode() {
+        let mut vt1 = Vt::builder()
+            .size(4, 1)
+            .wide_chars(WidthMode::EmojiWide)
+            .build();
 
-        // c) clear the whole line
+        vt1.feed_str("\u{263a}\u{2764}\u{fe0f}");
 
-        let mut vt = build_vt(4, 2, 2, 0, "abcd");
+        let vt2 = Vt::from_bytes(&vt1.to_bytes()).unwrap();
 
-        vt.feed_str("\x1b[2K");
+        assert_eq!(vt2.line(0).cells()[0].width(), 2);
+        assert_eq!(vt2.line(0).cells()[1].width(), 2);
+        assert_eq!(vt2.line(0), vt1.line(0));
 
-        assert_eq!(text(&vt), "  |\n");
+        let mut vt2 = vt2;
+        vt2.feed_str("\u{263a}");
 
-        // wrapped lines
+        assert_eq!(vt2.line(0).cells()[2].width(), 2);
+    }
 
-        // a) clear to the end of the line
+    #[test]
+    fn build_from_dump() {
+        let mut vt1 = Vt::new(10, 4);
 
-        let mut vt = Vt::new(4, 3);
+        vt1.feed_str("hello\n\rworld\x1b[2;3r\x1b[1;31m\x1b[?1049hfoo\x1b[?25l");
 
-        vt.feed_str("abcdefghij\x1b[A");
-        vt.feed_str("\x1b[0K");
+        let vt2 = Vt::builder().size(10, 4).build_from_dump(&vt1.dump());
 
-        assert_eq!(text(&vt), "abcd\nef|\nij");
-        assert_eq!(wrapped(&vt), vec![true, false, false]);
+        assert_vts_eq(&vt1, &vt2);
+    }
 
-        // b) clear to the beginning of the line
+    #[test]
+    fn keypad_app_mode() {
+        let mut vt = Vt::new(10, 4);
 
-        let mut vt = Vt::new(4, 3);
+        assert!(!vt.keypad_app_mode());
 
-        vt.feed_str("abcdefghij\x1b[A");
-        vt.feed_str("\x1b[1K");
+        vt.feed_str("\x1b=");
 
-        assert_eq!(text(&vt), "abcd\n  | h\nij");
-        assert_eq!(wrapped(&vt), vec![true, true, false]);
+        assert!(vt.keypad_app_mode());
 
-        // c) clear the whole line
+        let mut vt2 = Vt::new(10, 4);
+        vt2.feed_str(&vt.dump());
 
-        let mut vt = Vt::new(4, 3);
+        assert_vts_eq(&vt, &vt2);
 
-        vt.feed_str("abcdefghij\x1b[A");
-        vt.feed_str("\x1b[2K");
+        vt.feed_str("\x1b>");
 
-        assert_eq!(text(&vt), "abcd\n  |\nij");
-        assert_eq!(wrapped(&vt), vec![true, false, false]);
+        assert!(!vt.keypad_app_mode());
     }
 
     #[test]
-    fn execute_ed() {
-        // short lines
+    fn cursor_blink() {
+        let mut vt = Vt::new(10, 4);
 
-        // a) clear to the end of the screen
+        assert!(!vt.cursor_blink());
 
-        let mut vt = build_vt(4, 3, 1, 1, "abc\r\ndef\r\nghi");
+        vt.feed_str("\x1b[?12h");
 
-        vt.feed_str("\x1b[0J");
+        assert!(vt.cursor_blink());
 
-        assert_eq!(text(&vt), "abc\nd|\n");
+        let mut vt2 = Vt::new(10, 4);
+        vt2.feed_str(&vt.dump());
 
-        let mut vt = build_vt(4, 3, 1, 1, "abc\r\n\r\nghi");
+        assert_vts_eq(&vt, &vt2);
 
-        vt.feed_str("\x1b[0J");
+        vt.feed_str("\x1b[?12l");
 
-        assert_eq!(text(&vt), "abc\n |\n");
+        assert!(!vt.cursor_blink());
+    }
 
-        // b) clear to the beginning of the screen
+    #[test]
+    fn sixel_modes() {
+        let mut vt = Vt::new(10, 4);
 
-        let mut vt = build_vt(4, 3, 1, 1, "abc\r\ndef\r\nghi");
+        assert!(!vt.sixel_display_mode());
+        assert!(vt.private_color_registers());
 
-        vt.feed_str("\x1b[1J");
+        vt.feed_str("\x1b[?80h\x1b[?1070l");
 
-        assert_eq!(text(&vt), "\n | f\nghi");
+        assert!(vt.sixel_display_mode());
+        assert!(!vt.private_color_registers());
 
-        // c) clear the whole screen
+        let mut vt2 = Vt::new(10, 4);
+        vt2.feed_str(&vt.dump());
 
-        let mut vt = build_vt(4, 3, 1, 1, "abc\r\ndef\r\nghi");
+        assert_vts_eq(&vt, &vt2);
 
-        vt.feed_str("\x1b[2J");
+        vt.feed_str("\x1bc");
 
-        assert_eq!(text(&vt), "\n |\n");
+        assert!(!vt.sixel_display_mode());
+        assert!(vt.private_color_registers());
+    }
 
-        // wrapped lines
+    #[test]
+    fn origin_mode_and_scroll_region() {
+        let mut vt = Vt::new(10, 6);
 
-        // a) clear to the end of the screen
+        assert!(!vt.origin_mode());
+        assert_eq!(vt.scroll_region(), (0, 5));
 
-        let mut vt = build_vt(4, 3, 1, 1, "abcdefghij");
+        vt.feed_str("\x1b[2;4r\x1b[?6h");
 
-        vt.feed_str("\x1b[0J");
+        assert!(vt.origin_mode());
+        assert_eq!(vt.scroll_region(), (1, 3));
 
-        assert_eq!(text(&vt), "abcd\ne|\n");
-        assert_eq!(wrapped(&vt), vec![true, false, false]);
+        vt.feed_str("\x1b[?6l\x1b[r");
 
-        // b) clear to the beginning of the screen
+        assert!(!vt.origin_mode());
+        assert_eq!(vt.scroll_region(), (0, 5));
+    }
 
-        let mut vt = build_vt(4, 3, 1, 1, "abcdefghij");
+    #[test]
+    fn reverse_screen_mode() {
+        let mut vt = Vt::new(10, 4);
 
-        vt.feed_str("\x1b[1J");
+        assert!(!vt.reverse_screen_mode());
 
-        assert_eq!(text(&vt), "\n | gh\nij");
-        assert_eq!(wrapped(&vt), vec![false, true, false]);
+        let lines = vt.feed_str("\x1b[?5h").lines;
 
-        // c) clear the whole screen
+        assert_eq!(lines, vec![0, 1, 2, 3]);
+        assert!(vt.reverse_screen_mode());
 
-        let mut vt = build_vt(4, 3, 1, 1, "abcdefghij");
+        let mut vt2 = Vt::new(10, 4);
+        vt2.feed_str(&vt.dump());
 
-        vt.feed_str("\x1b[2J");
+        assert_vts_eq(&vt, &vt2);
 
-        assert_eq!(text(&vt), "\n |\n");
-        assert_eq!(wrapped(&vt), vec![false, false, false]);
+        vt.feed_str("\x1b[?5l");
+
+        assert!(!vt.reverse_screen_mode());
     }
 
     #[test]
-    fn execute_dch() {
-        let mut vt = build_vt(8, 2, 3, 0, "abcdefghijkl");
+    fn feed_reader() {
+        struct ByteReader<'a>(&'a [u8]);
 
-        vt.feed_str("\x1b[P");
+        impl std::io::Read for ByteReader<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                match self.0.split_first() {
+                    Some((byte, rest)) => {
+                        buf[0] = *byte;
+                        self.0 = rest;
 
-        assert_eq!(text(&vt), "abc|efgh\nijkl");
-        assert_eq!(wrapped(&vt), vec![false, false]);
+                        Ok(1)
+                    }
 
-        vt.feed_str("\x1b[2P");
+                    None => Ok(0),
+                }
+            }
+        }
 
-        assert_eq!(text(&vt), "abc|gh\nijkl");
+        let mut vt = Vt::new(8, 2);
+        let input = "zażółć\r\n".as_bytes();
+        let mut reader = ByteReader(input);
 
-        vt.feed_str("\x1b[10P");
+        assert_eq!(vt.feed_reader(&mut reader, |_| {}).unwrap(), input.len());
+        assert_eq!(text(&vt), "zażółć\n|");
 
-        assert_eq!(text(&vt), "abc|\nijkl");
+        let mut vt = Vt::new(8, 2);
 
-        vt.feed_str("\x1b[10C");
-        vt.feed_str("\x1b[10P");
+        vt.feed_reader(&mut &b"a\xffb\xc5"[..], |_| {}).unwrap();
 
-        assert_eq!(text(&vt), "abc    |\nijkl");
+        assert_eq!(text(&vt), "a\u{fffd}b\u{fffd}|\n");
+
+        // changes of every read are passed on
+
+        let mut vt = Vt::builder().size(4, 2).scrollback_limit(0).build();
+        let mut responses = Vec::new();
+        let mut scrollback = Vec::new();
+        let mut reader = ByteReader(b"a\r\nb\r\nc\x1b[6n");
+
+        vt.feed_reader(&mut reader, |changes| {
+            responses.extend(changes.response);
+            scrollback.extend(changes.scrollback.map(|l| l.text()));
+        })
+        .unwrap();
+
+        assert_eq!(responses, vec!["\x1b[2;2R"]);
+        assert_eq!(scrollback, vec!["a   "]);
     }
 
     #[test]
-    fn execute_ech() {
-        let mut vt = build_vt(8, 2, 3, 0, "abcdefghijkl");
+    fn feed_reader_invalid_utf8() {
+        use std::io::Read;
 
-        vt.feed_str("\x1b[X");
+        // overlong encoding, lone continuation byte, encoded surrogate,
+        // truncated multibyte sequences, interleaved with escape sequences
+        let input: &[u8] =
+            b"a\xc0\xafb\x1b[1m\x80c\xed\xa0\x80d\xe2\x82\x1b[0me\xf0\x9f\x98f\x1b[2;1Hg\xe2";
 
-        assert_eq!(text(&vt), "abc| efgh\nijkl");
-        assert_eq!(wrapped(&vt), vec![true, false]);
+        let mut expected = Vt::new(20, 3);
+        expected.feed_str(&String::from_utf8_lossy(input));
 
-        vt.feed_str("\x1b[2X");
+        assert_eq!(
+            text(&expected),
+            "a\u{fffd}\u{fffd}b\u{fffd}c\u{fffd}\u{fffd}\u{fffd}d\u{fffd}e\u{fffd}f\ng\u{fffd}|\n"
+        );
 
-        assert_eq!(text(&vt), "abc|  fgh\nijkl");
-        assert_eq!(wrapped(&vt), vec![true, false]);
+        for i in 0..=input.len() {
+            let mut vt = Vt::new(20, 3);
+            let mut reader = (&input[..i]).chain(&input[i..]);
 
-        vt.feed_str("\x1b[10X");
+            assert_eq!(vt.feed_reader(&mut reader, |_| {}).unwrap(), input.len());
 
-        assert_eq!(text(&vt), "abc|\nijkl");
-        assert_eq!(wrapped(&vt), vec![false, false]);
+            assert_vts_eq(&vt, &expected);
+            assert_eq!(vt.view(), expected.view());
+        }
+    }
 
-        vt.feed_str("\x1b[3C\x1b[X");
+    #[test]
+    fn feed_events() {
+        let mut vt = Vt::builder()
+            .size(4, 2)
+            .scrollback_limit(0)
+            .resizable(true)
+            .build();
+
+        assert_eq!(
+            vt.feed_events(""),
+            vec![VtEvent::LineChanged(0), VtEvent::LineChanged(1)]
+        );
+
+        assert_eq!(
+            vt.feed_events("ab"),
+            vec![VtEvent::LineChanged(0), VtEvent::CursorMoved]
+        );
+
+        assert_eq!(
+            vt.feed_events("\x1b]2;hello\x07\x07"),
+            vec![VtEvent::TitleChanged, VtEvent::Bell]
+        );
+        assert_eq!(vt.title(), "hello");
+
+        assert_eq!(
+            vt.feed_events("\r\n\r\n\x1b[5n"),
+            vec![
+                VtEvent::ScrolledBack(1),
+                VtEvent::LineChanged(0),
+                VtEvent::LineChanged(1),
+                VtEvent::CursorMoved,
+                VtEvent::Response("\x1b[0n".to_owned())
+            ]
+        );
+
+        let events = vt.feed_events("\x1b[8;3;5t");
+
+        assert_eq!(events[0], VtEvent::Resized(5, 3));
+
+        let mut vt2 = Vt::new(5, 3);
+        vt2.feed_str(&vt.dump());
+
+        assert_vts_eq(&vt, &vt2);
+    }
 
-        assert_eq!(text(&vt), "abc   |\nijkl");
+    #[test]
+    fn feed_events_buffer_switch() {
+        let mut vt = Vt::new(4, 2);
+        vt.feed_events("");
+
+        let events = vt.feed_events("\x1b[?1049h");
+
+        assert_eq!(
+            events[0],
+            VtEvent::BufferSwitched(BufferTransition::EnteredAlternate)
+        );
+        assert!(vt.is_alternate_buffer());
+
+        let events = vt.feed_events("\x1b[?1049l\x1b[?1047h\x1b[?1047l");
+
+        assert_eq!(
+            &events[..3],
+            [
+                VtEvent::BufferSwitched(BufferTransition::LeftAlternate),
+                VtEvent::BufferSwitched(BufferTransition::EnteredAlternate),
+                VtEvent::BufferSwitched(BufferTransition::LeftAlternate)
+            ]
+        );
+        assert!(!vt.is_alternate_buffer());
+
+        vt.feed_str("\x1b[?1047h");
+
+        assert_eq!(
+            vt.feed_events("\x1bc")[0],
+            VtEvent::BufferSwitched(BufferTransition::LeftAlternate)
+        );
     }
 
     #[test]
-    fn execute_cht() {
-        let mut vt = build_vt(28, 1, 3, 0, "abcdefghijklmnopqrstuwxyzabc");
+    fn set_scrollback_limit() {
+        let mut vt = Vt::builder().size(4, 2).scrollback_limit(10).build();
 
-        vt.feed_str("\x1b[I");
+        vt.feed_str("a\r\nb\r\nc\r\nd\r\ne\r\nf");
 
-        assert_eq!(vt.cursor(), (8, 0));
+        assert_eq!(vt.scrollback_len(), 4);
 
-        vt.feed_str("\x1b[2I");
+        let evicted: Vec<String> = vt
+            .set_scrollback_limit(Some(1))
+            .map(|l| l.text().trim_end().to_owned())
+            .collect();
 
-        assert_eq!(vt.cursor(), (24, 0));
+        assert_eq!(evicted, vec!["a", "b", "c"]);
+        assert_eq!(vt.scrollback_len(), 1);
+        assert_eq!(vt.set_scrollback_limit(None).count(), 0);
 
-        vt.feed_str("\x1b[I");
+        vt.feed_str("\r\ng\r\nh\r\ni");
 
-        assert_eq!(vt.cursor(), (27, 0));
-    }
+        assert_eq!(vt.scrollback_len(), 4);
 
-    #[test]
-    fn execute_cbt() {
-        let mut vt = build_vt(28, 1, 26, 0, "abcdefghijklmnopqrstuwxyzabc");
+        vt.feed_str("\x1b[?1049h");
 
-        vt.feed_str("\x1b[Z");
+        assert_eq!(vt.set_scrollback_limit(Some(0)).count(), 4);
 
-        assert_eq!(vt.cursor(), (24, 0));
+        vt.feed_str("\x1b[?1049l\r\nj\r\nk");
 
-        vt.feed_str("\x1b[2Z");
+        assert_eq!(vt.scrollback_len(), 0);
+        assert_eq!(text(&vt), "j\nk|");
+    }
 
-        assert_eq!(vt.cursor(), (8, 0));
+    #[test]
+    fn top_and_bottom_line() {
+        let mut vt = Vt::new(4, 3);
 
-        vt.feed_str("\x1b[Z");
+        vt.feed_str("a\r\nb\r\nc\r\nd\x1b[1;1H");
 
-        assert_eq!(vt.cursor(), (0, 0));
+        assert_eq!(vt.top_line().text(), "b   ");
+        assert_eq!(vt.bottom_line().text(), "d   ");
+        assert_eq!(vt.scrollback_len(), 1);
     }
 
     #[test]
-    fn execute_sc_rc() {
-        // DECSC/DECRC variant
+    fn word_at() {
+        let mut vt = Vt::new(8, 4);
 
-        let mut vt = build_vt(4, 3, 0, 0, "");
+        vt.feed_str("foo bar_1 baz\r\n(x) \u{4f60}\u{597d}e\u{301}!");
 
-        // move 2x right, 1 down
-        vt.feed_str("  \n");
+        assert_eq!(vt.word_at(0, 0), Some((0, 3, 0, "foo".to_owned())));
+        assert_eq!(vt.word_at(2, 0), Some((0, 3, 0, "foo".to_owned())));
+        assert_eq!(vt.word_at(3, 0), None);
+        assert_eq!(vt.word_at(5, 0), Some((4, 9, 0, "bar_1".to_owned())));
+        assert_eq!(vt.word_at(0, 1), Some((4, 9, 0, "bar_1".to_owned())));
+        assert_eq!(vt.word_at(2, 1), Some((2, 5, 1, "baz".to_owned())));
+        assert_eq!(vt.word_at(1, 2), Some((1, 2, 2, "x".to_owned())));
+        assert_eq!(vt.word_at(0, 3), None);
+        assert_eq!(vt.word_at(8, 0), None);
+        assert_eq!(vt.word_at(0, 4), None);
 
-        // save cursor
-        vt.feed_str("\x1b7");
+        let word = Some((4, 7, 2, "\u{4f60}\u{597d}e\u{301}".to_owned()));
 
-        // move 1x right, 1x down
-        vt.feed_str(" \n");
+        assert_eq!(vt.word_at(5, 2), word);
+        assert_eq!(vt.word_at(6, 2), word);
+        assert_eq!(vt.word_at(7, 2), None);
 
-        // restore cursor
-        vt.feed_str("\x1b8");
+        let word = vt.word_at_with(1, 0, |ch| !ch.is_whitespace());
 
-        assert_eq!(vt.cursor(), (2, 1));
+        assert_eq!(word, Some((0, 3, 0, "foo".to_owned())));
+
+        let word = vt.word_at_with(0, 1, |ch| ch.is_alphabetic());
+
+        assert_eq!(word, None);
+    }
+
+    #[test]
+    fn prompt_unselectable() {
+        let mut vt = Vt::new(10, 3);
+
+        vt.feed_str("\x1b]133;A\x07\x1b[32m$\x1b[0m \x1b]133;B\x07ls -l\r\n");
+        vt.feed_str("foo\r\n\x1b]133;A;k=i\x1b\\> ");
+
+        let line = vt.line(0);
+
+        assert!(!line.cells()[0].is_selectable());
+        assert!(!line.cells()[1].is_selectable());
+        assert!(line.cells()[2].is_selectable());
+        assert!(vt.line(1).cells().iter().all(|c| c.is_selectable()));
+        assert_eq!(vt.unselectable_zones(), vec![(0, 0..2), (2, 0..2)]);
+
+        let mut vt2 = Vt::new(10, 3);
+        vt2.feed_str(&vt.dump());
 
-        // ansi.sys variant
+        assert_vts_eq(&vt, &vt2);
 
-        let mut vt = build_vt(4, 3, 0, 0, "");
+        vt.feed_str("\x1bc");
 
-        // move 2x right, 1 down
-        vt.feed_str("  \n");
+        assert!(vt.unselectable_zones().is_empty());
+    }
 
-        // save cursor
-        vt.feed_str("\x1b[s");
+    #[test]
+    fn touch_all() {
+        let mut vt = Vt::new(4, 3);
+        vt.feed_str("abc");
 
-        // move 1x right, 1x down
-        vt.feed_str(" \n");
+        assert!(vt.feed_str("").lines.is_empty());
 
-        // restore cursor
-        vt.feed_str("\x1b[u");
+        let generation = vt.generation();
+        vt.touch_all();
 
-        assert_eq!(vt.cursor(), (2, 1));
+        assert_eq!(vt.feed_str("").lines, vec![0, 1, 2]);
+        assert!(vt.generation() > generation);
+        assert_eq!(text(&vt), "abc|\n\n");
     }
 
     #[test]
-    fn execute_rep() {
-        let mut vt = build_vt(20, 2, 0, 0, "");
+    fn feed_str_segments() {
+        let mut vt = Vt::new(4, 3);
+        vt.feed_str("");
 
-        vt.feed_str("\x1b[b"); // REP
+        let segments = vt.feed_str_segments("\x1b[2;1Hab\x1b[1mc");
 
-        assert_eq!(text(&vt), "|\n");
+        assert_eq!(segments.len(), 1);
 
-        vt.feed_str("A");
-        vt.feed_str("\x1b[b");
+        let (row, segments) = &segments[0];
+        let text: Vec<String> = segments
+            .iter()
+            .map(|cells| cells.iter().map(|c| c.char()).collect())
+            .collect();
 
-        assert_eq!(text(&vt), "AA|\n");
+        assert_eq!(*row, 1);
+        assert_eq!(text, vec!["ab", "c", " "]);
+        assert!(segments[1][0].pen().is_bold());
+        assert_eq!(segments[2][0].pen(), segments[0][0].pen());
+        assert_ne!(segments[0][0].pen(), segments[1][0].pen());
+        assert!(vt.feed_str_segments("\x1b[1;1H").is_empty());
+    }
 
-        vt.feed_str("\x1b[3b");
+    #[test]
+    fn feed_str_segments_pending_changes() {
+        let mut vt = Vt::new(4, 3);
+        vt.feed_str("");
 
-        assert_eq!(text(&vt), "AAAAA|\n");
+        assert_eq!(vt.feed_str_segments("\x1b[6n\x1b[?1049h").len(), 3);
 
-        vt.feed_str("\x1b[5C"); // move 5 cols to the right
-        vt.feed_str("\x1b[b");
+        let changes = vt.feed_str("");
 
-        assert_eq!(text(&vt), "AAAAA      |\n");
+        assert_eq!(changes.response.as_deref(), Some("\x1b[1;1R"));
+        assert_eq!(changes.buffer_transitions.len(), 1);
     }
 
     #[test]
-    fn execute_xtwinops_wider() {
-        let mut builder = Vt::builder();
-        builder.resizable(true);
+    fn feed_str_changed() {
+        let mut vt = Vt::new(10, 3);
 
-        let mut vt = builder.size(6, 6).build();
+        assert!(vt.feed_str_changed("foo\r\nbar"));
+        assert!(!vt.feed_str_changed("\x1b[1;1Hfoo\x1b[2;1Hbar"));
+        assert!(vt.feed_str_changed("\x1b[1;1Hfox"));
+        assert!(vt.feed_str_changed("\x1b[1;1H\x1b[1mfox"));
+        assert!(!vt.feed_str_changed("\x1b[?1049h\x1b[?1049l"));
+        assert!(vt.feed_str_changed("\x1b[?1049h"));
 
-        vt.feed_str("\x1b[8;6;7t");
+        // the rest of the changes are left for feed_str
+        vt.feed_str("");
 
-        assert_eq!(text(&vt), "|\n\n\n\n\n");
-        assert!(!vt.view().iter().any(|l| l.wrapped));
+        assert!(!vt.feed_str_changed("\x1b[6n\x1b[?1049l\x1b[?1049h"));
 
-        vt.feed_str("\x1b[8;6;15t");
+        let changes = vt.feed_str("");
 
-        assert_eq!(text(&vt), "|\n\n\n\n\n");
-        assert!(!vt.view().iter().any(|l| l.wrapped));
+        assert_eq!(changes.response.as_deref(), Some("\x1b[1;4R"));
+        assert_eq!(changes.buffer_transitions.len(), 2);
+    }
 
-        let mut vt = builder.size(6, 6).build();
+    #[test]
+    fn generation() {
+        let mut vt = Vt::builder().size(10, 4).resizable(true).build();
 
-        vt.feed_str("000000111111222222333333444444555");
+        assert_eq!(vt.generation(), 0);
 
-        assert_eq!(text(&vt), "000000\n111111\n222222\n333333\n444444\n555|");
-        assert_eq!(wrapped(&vt), vec![true, true, true, true, true, false]);
+        vt.feed_str("ab");
+        let g1 = vt.generation();
 
-        vt.feed_str("\x1b[8;6;7t");
+        assert!(g1 > 0);
 
-        assert_eq!(text(&vt), "0000001\n1111122\n2222333\n3334444\n44555|\n");
-        assert_eq!(wrapped(&vt), vec![true, true, true, true, false, false]);
+        vt.feed_str("\x1b[6n\x1b[");
 
-        vt.feed_str("\x1b[8;6;15t");
+        assert_eq!(vt.generation(), g1);
 
-        assert_eq!(text(&vt), "000000111111222\n222333333444444\n555|\n\n\n");
-        assert_eq!(wrapped(&vt), vec![true, true, false, false, false, false]);
+        vt.feed_str("2;5H");
+        let g2 = vt.generation();
 
-        let mut vt = builder.size(4, 3).build();
+        assert!(g2 > g1);
 
-        vt.feed_str("000011\r\n22");
+        vt.feed_str("\x1b[8;5;20t");
 
-        assert_eq!(text(&vt), "0000\n11\n22|");
-        assert_eq!(wrapped(&vt), vec![true, false, false]);
+        assert!(vt.generation() > g2);
 
-        vt.feed_str("\x1b[8;3;8t");
+        vt.feed_str("\x1bc");
 
-        assert_eq!(text(&vt), "000011\n22|\n");
-        assert_eq!(wrapped(&vt), vec![false, false, false]);
+        assert_eq!(vt.generation(), 0);
     }
 
     #[test]
-    fn execute_xtwinops_narrower() {
-        let mut builder = Vt::builder();
-        builder.resizable(true);
+    fn input_modes() {
+        let mut vt = Vt::new(10, 4);
+        let modes = vt.input_modes();
+
+        assert!(!modes.cursor_keys_app_mode);
+        assert!(!modes.keypad_app_mode);
+        assert!(!modes.bracketed_paste);
+        assert!(!modes.focus_reporting);
+        assert_eq!(modes.mouse_mode, MouseMode::Off);
+        assert_eq!(modes.mouse_encoding, MouseEncoding::Default);
+
+        vt.feed_str("\x1b[?1h\x1b=\x1b[?2004h\x1b[?1004h\x1b[?1000h\x1b[?1002h\x1b[?1006h");
+        let modes = vt.input_modes();
+
+        assert!(modes.cursor_keys_app_mode);
+        assert!(modes.keypad_app_mode);
+        assert!(modes.bracketed_paste);
+        assert!(modes.focus_reporting);
+        assert_eq!(modes.mouse_mode, MouseMode::ButtonEvent);
+        assert_eq!(modes.mouse_encoding, MouseEncoding::Sgr);
 
-        let mut vt = builder.size(15, 6).build();
+        let mut vt2 = Vt::new(10, 4);
+        vt2.feed_str(&vt.dump());
 
-        vt.feed_str("\x1b[8;6;7t");
+        assert_vts_eq(&vt, &vt2);
 
-        assert_eq!(text(&vt), "|\n\n\n\n\n");
-        assert!(!vt.view().iter().any(|l| l.wrapped));
+        vt.feed_str("\x1b[?1000l");
 
-        vt.feed_str("\x1b[8;6;6t");
+        assert_eq!(vt.input_modes().mouse_mode, MouseMode::Off);
 
-        assert_eq!(text(&vt), "|\n\n\n\n\n");
-        assert!(!vt.view().iter().any(|l| l.wrapped));
+        vt.feed_str("\x1b[?1003h\x1b[?9l");
 
-        let mut vt = builder.size(8, 2).build();
+        assert_eq!(vt.input_modes().mouse_mode, MouseMode::Off);
 
-        vt.feed_str("\nabcdef");
+        vt.feed_str("\x1b[?1002l\x1b[?1006l\x1b[?1004l\x1b[?2004l");
+        let modes = vt.input_modes();
 
-        assert_eq!(wrapped(&vt), vec![false, false]);
+        assert!(!modes.bracketed_paste);
+        assert!(!modes.focus_reporting);
+        assert_eq!(modes.mouse_mode, MouseMode::Off);
+        assert_eq!(modes.mouse_encoding, MouseEncoding::Default);
+    }
 
-        vt.feed_str("\x1b[8;;4t");
+    #[test]
+    fn dump_mouse_modes() {
+        let modes = ["", "\x1b[?9h", "\x1b[?1000h", "\x1b[?1002h", "\x1b[?1003h"];
+        let encodings = ["", "\x1b[?1005h", "\x1b[?1006h", "\x1b[?1015h"];
 
-        assert_eq!(text(&vt), "abcd\nef|");
-        assert_eq!(wrapped(&vt), vec![true, false]);
+        for mode in modes {
+            for encoding in encodings {
+                let mut vt1 = Vt::new(10, 4);
+                vt1.feed_str(mode);
+                vt1.feed_str(encoding);
 
-        let mut vt = builder.size(15, 6).build();
+                let mut vt2 = Vt::new(10, 4);
+                vt2.feed_str(&vt1.dump());
 
-        vt.feed_str("000000111111222222333333444444555");
+                assert_eq!(vt2.input_modes(), vt1.input_modes());
+            }
+        }
 
-        assert_eq!(text(&vt), "000000111111222\n222333333444444\n555|\n\n\n");
-        assert_eq!(wrapped(&vt), vec![true, true, false, false, false, false]);
+        let mut vt = Vt::new(10, 4);
+        vt.feed_str("\x1b[?1003h\x1b[?1006h");
 
-        vt.feed_str("\x1b[8;6;7t");
+        let mut vt2 = Vt::new(10, 4);
+        vt2.feed_str(&vt.dump());
 
-        assert_eq!(text(&vt), "2222333\n3334444\n44555|\n\n\n");
-        assert_eq!(wrapped(&vt), vec![true, true, false, false, false, false]);
+        assert_eq!(vt2.input_modes().mouse_mode, MouseMode::AnyEvent);
+        assert_eq!(vt2.input_modes().mouse_encoding, MouseEncoding::Sgr);
+    }
 
-        vt.feed_str("\x1b[8;6;6t");
+    #[test]
+    fn dump_with_file() {
+        if let Ok((w, h, input, step)) = setup_dump_with_file() {
+            let mut vt1 = Vt::new(w, h);
 
-        assert_eq!(text(&vt), "333333\n444444\n555|\n\n\n");
-        assert_eq!(wrapped(&vt), vec![true, true, false, false, false, false]);
+            let mut s = 0;
+
+            for c in input.chars().take(1_000_000) {
+                vt1.feed(c);
+
+                if s == 0 {
+                    let d = vt1.dump();
+                    let mut vt2 = Vt::new(w, h);
+
+                    vt2.feed_str(&d);
+
+                    assert_vts_eq(&vt1, &vt2);
+                }
+
+                s = (s + 1) % step;
+            }
+        }
     }
 
     #[test]
-    fn execute_xtwinops() {
-        let mut vt = Vt::builder().size(8, 4).resizable(true).build();
-        vt.feed_str("abcdefgh\r\nijklmnop\r\nqrstuw");
-        vt.feed_str("\x1b[4;1H");
+    fn parser_state() {
+        let mut vt = Vt::new(10, 4);
 
-        let resized = vt.feed_str("AAA").resized;
+        assert_eq!(vt.parser_state(), State::Ground);
 
-        assert!(!resized);
+        vt.feed_str("\x1b]0;tit");
 
-        let resized = vt.feed_str("\x1b[8;5;t").resized;
+        assert_eq!(vt.parser_state(), State::OscString);
 
-        assert!(resized);
-        assert_eq!(text(&vt), "abcdefgh\nijklmnop\nqrstuw\nAAA|\n");
+        vt.feed_str("le\x07");
 
-        vt.feed_str("BBBBB");
+        assert_eq!(vt.parser_state(), State::Ground);
+    }
 
-        assert_eq!(vt.cursor(), (8, 3));
+    #[test]
+    fn unknown_sequences() {
+        let input =
+            "a\x1b[?1;2;3$pb\x1b[5Jc\x1b[1:2:3xd\x1bQe\x1b[1;31mf\x1b[>4;1m\u{9b}?5$y\x1b[1;2?x";
 
-        let resized = vt.feed_str("\x1b[8;;4t").resized;
+        let mut vt = Vt::builder()
+            .size(10, 2)
+            .collect_unknown_sequences(true)
+            .build();
 
-        assert!(resized);
-        assert_eq!(text(&vt), "qrst\nuw\nAAAB\nBBB|B\n");
+        vt.feed_str(input);
 
-        vt.feed_str("\rCCC");
+        assert_eq!(vt.parser_state(), State::Ground);
+        assert_eq!(text(&vt), "abcdef|\n");
 
-        assert_eq!(text(&vt), "qrst\nuw\nAAAB\nCCC|B\n");
-        assert_eq!(wrapped(&vt), vec![true, false, true, false, false]);
+        assert_eq!(
+            vt.take_unknown_sequences(),
+            vec![
+                "\x1b[?1;2;3$p",
+                "\x1b[5J",
+                "\x1b[1:2:3x",
+                "\x1bQ",
+                "\x1b[>4;1m",
+                "\u{9b}?5$y",
+                "\x1b[1;2?x",
+            ]
+        );
 
-        vt.feed_str("\x1b[8;;3t");
+        assert!(vt.take_unknown_sequences().is_empty());
 
-        assert_eq!(text(&vt), "tuw\nAAA\nBCC\nC|B\n");
+        vt.feed_str("\x1b]0;title\x1b\\\x1bPqab\x1b\\\x1b_x\x1b\\");
 
-        vt.feed_str("\x1b[8;;5t");
+        assert!(vt.take_unknown_sequences().is_empty());
 
-        assert_eq!(text(&vt), "qrstu\nw\nAAABC\nCC|B\n");
+        vt.feed_str("\x1b[?2026h\x1b[?9999h\x1b[?25;2026l\x1b[21h\x1b[?25h\x1b[4l");
 
-        vt.feed_str("DDD");
-        vt.feed_str("\x1b[8;;6t");
+        assert_eq!(
+            vt.take_unknown_sequences(),
+            vec!["\x1b[?2026h", "\x1b[?9999h", "\x1b[?25;2026l", "\x1b[21h"]
+        );
+        assert!(vt.cursor().visible);
 
-        assert_eq!(text(&vt), "op\nqrstuw\nAAABCC\nCDDD|\n");
-    }
+        let mut vt = Vt::builder()
+            .size(10, 2)
+            .max_string_len(8)
+            .collect_unknown_sequences(true)
+            .build();
 
-    #[test]
-    fn execute_xtwinops_noop() {
-        let mut vt = Vt::new(8, 4);
+        vt.feed_str(&format!("\x1b[{}x", "1;".repeat(100)));
 
-        let resized = vt.feed_str("\x1b[8;;t").resized;
+        assert_eq!(vt.take_unknown_sequences(), vec!["\x1b[1;1;1;"]);
 
-        assert!(!resized);
+        let mut vt = Vt::new(10, 2);
+        vt.feed_str(input);
+
+        assert!(vt.take_unknown_sequences().is_empty());
     }
 
     #[test]
-    fn execute_xtwinops_taller() {
-        let mut vt = Vt::builder().size(6, 4).resizable(true).build();
+    fn apc_strings() {
+        let mut vt = Vt::builder().size(10, 4).collect_apc_strings(true).build();
 
-        vt.feed_str("AAA\n\rBBB\n\r");
-        let resized = vt.feed_str("\x1b[8;5;;t").resized;
+        vt.feed_str("a\x1b_Gf=100;AAAA\x1b\\b\u{9f}tmux\u{9c}\x1b_par");
 
-        assert!(resized);
-        assert_eq!(text(&vt), "AAA\nBBB\n|\n\n");
-    }
+        assert_eq!(vt.take_apc_strings(), vec!["Gf=100;AAAA", "tmux"]);
+        assert_eq!(text(&vt), "ab|\n\n\n");
 
-    #[test]
-    fn execute_xtwinops_shorter() {
-        let mut vt = Vt::builder().size(6, 6).resizable(true).build();
+        let mut vt2 = Vt::new(10, 4);
+        vt2.feed_str(&vt.dump());
 
-        vt.feed_str("AAA\n\rBBB\n\rCCC\n\r");
+        assert_vts_eq(&vt, &vt2);
 
-        let resized = vt.feed_str("\x1b[8;5;;t").resized;
+        vt.feed_str("tial\x1b\\");
 
-        assert!(resized);
-        assert_eq!(text(&vt), "AAA\nBBB\nCCC\n|\n");
+        assert_eq!(vt.take_apc_strings(), vec!["partial"]);
+        assert!(vt.take_apc_strings().is_empty());
 
-        let resized = vt.feed_str("\x1b[8;3;;t").resized;
+        let mut vt = Vt::builder()
+            .size(10, 4)
+            .max_string_len(3)
+            .collect_apc_strings(true)
+            .build();
 
-        assert!(resized);
-        assert_eq!(text(&vt), "BBB\nCCC\n|");
+        vt.feed_str("\x1b_abc\x1b\\\x1b_abcd\x1b\\");
 
-        let resized = vt.feed_str("\x1b[8;2;;t").resized;
+        assert_eq!(vt.take_apc_strings(), vec!["abc"]);
 
-        assert!(resized);
-        assert_eq!(text(&vt), "CCC\n|");
+        vt.feed_str("\x1b_ab\x18\x1b_ab\x1a\x1b_ab\x1b[m");
+
+        assert!(vt.take_apc_strings().is_empty());
+
+        // not collected unless enabled
+        let mut vt = Vt::new(10, 4);
+
+        vt.feed_str("\x1b_abc\x1b\\");
+
+        assert!(vt.take_apc_strings().is_empty());
     }
 
     #[test]
-    fn execute_xtwinops_vs_buffer_switching() {
-        let mut vt = Vt::builder().size(4, 4).resizable(true).build();
+    fn vt52_mode() {
+        let mut vt = Vt::new(10, 4);
 
-        // fill primary buffer
-        vt.feed_str("aaa\n\rbbb\n\rc\n\rddd");
+        vt.feed_str("\x1b[?2l");
 
-        assert_eq!(vt.cursor(), (3, 3));
+        assert!(vt.vt52_mode());
 
-        // resize to 4x5
-        vt.feed_str("\x1b[8;5;4;t");
+        vt.feed_str("ab\x1bY\x22\x25c\x1bAd\x1bHe");
 
-        assert_eq!(text(&vt), "aaa\nbbb\nc\nddd|\n");
+        assert_eq!(text(&vt), "e|b\n      d\n     c\n");
+        assert_eq!(vt.feed_str("\x1bZ").response.as_deref(), Some("\x1b/Z"));
 
-        // switch to alternate buffer
-        vt.feed_str("\x1b[?1049h");
+        vt.feed_str("\x1bY\x21");
 
-        assert_eq!(vt.cursor(), (3, 3));
+        let mut vt2 = Vt::new(10, 4);
+        vt2.feed_str(&vt.dump());
 
-        // resize to 4x2
-        vt.feed_str("\x1b[8;2;4t");
+        assert_vts_eq(&vt, &vt2);
 
+        vt.feed_str("\x20\x1b<\x1b[3C");
+
+        assert!(!vt.vt52_mode());
         assert_eq!(vt.cursor(), (3, 1));
 
-        // resize to 2x3, we'll check later if primary buffer preserved more columns
-        vt.feed_str("\x1b[8;3;2t");
+        let mut vt = Vt::new(10, 4);
+        vt.execute(Function::Decrst(vec![DecMode::Ansi]));
 
-        // resize to 3x3
-        vt.feed_str("\x1b[8;3;3t");
+        assert!(vt.vt52_mode());
 
-        // switch back to primary buffer
-        vt.feed_str("\x1b[?1049l");
+        vt.feed_str("\x1bY\x21\x22a");
 
-        assert_eq!(text(&vt), "bbb\nc\ndd|d");
+        assert_eq!(text(&vt), "\n  a|\n\n");
     }
 
     #[test]
-    fn dump_initial() {
-        let vt1 = Vt::new(10, 4);
-        let mut vt2 = Vt::new(10, 4);
+    fn vt52_mode_keys() {
+        let mut vt = Vt::new(10, 4);
+        let none = Modifiers::default();
 
-        vt2.feed_str(&vt1.dump());
+        vt.feed_str("\x1b[?2l");
 
-        assert_vts_eq(&vt1, &vt2);
-    }
+        assert!(vt.input_modes().vt52_mode);
+        assert_eq!(vt.encode_key(Key::Up, none).as_deref(), Some("\x1bA"));
 
-    #[test]
-    fn dump_modified() {
-        let mut vt1 = Vt::new(10, 4);
-        let mut vt2 = Vt::new(10, 4);
+        vt.feed_str("\x1b=");
 
-        vt1.feed_str("hello\n\rworld\u{9b}5W\u{9b}7`\u{1b}[W\u{9b}?6h");
-        vt1.feed_str("\u{9b}2;4r\u{9b}1;5H\x1b[1;31;41m\u{9b}?25l\u{9b}4h");
-        vt1.feed_str("\u{9b}?7l\u{9b}20h\u{9b}\u{3a}\x1b(0\x1b)0\u{0e}");
+        assert_eq!(
+            vt.encode_key(Key::Keypad('1'), none).as_deref(),
+            Some("\x1b?q")
+        );
 
-        vt2.feed_str(&vt1.dump());
+        vt.feed_str("\x1b<");
 
-        assert_vts_eq(&vt1, &vt2);
+        assert!(!vt.input_modes().vt52_mode);
+        assert_eq!(vt.encode_key(Key::Up, none).as_deref(), Some("\x1b[A"));
     }
 
     #[test]
-    fn dump_with_file() {
-        if let Ok((w, h, input, step)) = setup_dump_with_file() {
-            let mut vt1 = Vt::new(w, h);
+    fn sixels() {
+        let mut vt = Vt::new(10, 4);
 
-            let mut s = 0;
+        vt.feed_str("ab\r\ncd\x1bPq#0~~\x1b\\\r\n");
 
-            for c in input.chars().take(1_000_000) {
-                vt1.feed(c);
+        assert_eq!(vt.sixels().len(), 1);
+        assert_eq!(vt.sixels()[0].row, 1);
+        assert_eq!(vt.sixels()[0].col, 2);
+        assert_eq!(vt.sixels()[0].data, "#0~~");
 
-                if s == 0 {
-                    let d = vt1.dump();
-                    let mut vt2 = Vt::new(w, h);
+        vt.feed_str("\x1b[1;1H\x1bM");
 
-                    vt2.feed_str(&d);
+        assert_eq!(vt.sixels()[0].row, 2);
 
-                    assert_vts_eq(&vt1, &vt2);
-                }
+        vt.feed_str("\x1b[4;1H\r\n\r\n");
 
-                s = (s + 1) % step;
-            }
-        }
+        assert_eq!(vt.sixels()[0].row, 0);
+
+        vt.feed_str("\r\n");
+
+        assert!(vt.sixels().is_empty());
+
+        vt.feed_str("\u{90}q#1~\u{9c}\x1b[2J");
+
+        assert!(vt.sixels().is_empty());
     }
 
     #[test]
@@ -1236,6 +3913,40 @@ mod tests {
         (0x20..=0x7fu8).prop_map(|v| v as char)
     }
 
+    // sequences moving the cursor around scroll margins in and out of origin
+    // mode, with saved contexts (DECSC, SCOSC) in play
+    fn gen_origin_input(max_len: usize) -> impl Strategy<Value = String> {
+        let fixed = prop::sample::select(vec![
+            "\x1b[?6h",
+            "\x1b[?6l",
+            "\x1b7",
+            "\x1b8",
+            "\x1b[s",
+            "\x1b[u",
+            "\x1b[?7l",
+            "\x1b[?7h",
+            "\x1b(0",
+            "\x1b(B",
+            "\x1b)0",
+            "\x0e",
+            "\x0f",
+            "\x1b[1;4m",
+            "\x1b[0m",
+            "ab",
+            "\n",
+        ])
+        .prop_map(|s| s.to_owned());
+
+        let margins = (1..=5u8, 1..=5u8).prop_map(|(t, b)| format!("\x1b[{t};{b}r"));
+        let cup = (1..=5u8, 1..=10u8).prop_map(|(r, c)| format!("\x1b[{r};{c}H"));
+
+        let relative = (1..=4u8, prop::sample::select(vec!['A', 'B', 'C', 'D']))
+            .prop_map(|(n, f)| format!("\x1b[{n}{f}"));
+
+        prop::collection::vec(prop_oneof![fixed, margins, cup, relative], 1..=max_len)
+            .prop_map(|seqs| seqs.concat())
+    }
+
     fn flatten<T, I: IntoIterator<Item = T>>(seqs: Vec<I>) -> Vec<T> {
         seqs.into_iter().flatten().collect()
     }
@@ -1285,6 +3996,25 @@ mod tests {
             assert!(vt.lines().len() >= vt.size().1);
         }
 
+        #[test]
+        fn prop_scrolling_small_top_region_after_narrowing(
+            new_cols in 1..10usize,
+            new_rows in 1..5usize,
+            bottom in 1..5usize,
+            n in 0..4usize,
+            input in gen_input(25),
+        ) {
+            let mut vt = Vt::builder().size(10, 5).scrollback_limit(2).resizable(true).build();
+
+            vt.feed_str(&(input.into_iter().collect::<String>()));
+            vt.feed_str(&format!("\x1b[8;{};{}t", new_rows, new_cols));
+            vt.feed_str(&format!("\x1b[1;{}r\x1b[{}L\x1b[{}S", bottom, n, n));
+            vt.feed_str(&format!("\x1b[{}H\n\n\x1b[{}M\x1bD", bottom, n));
+
+            vt.terminal.verify();
+            assert!(vt.lines().len() >= vt.size().1);
+        }
+
         #[test]
         fn prop_dump(input in gen_input(25)) {
             let mut vt1 = Vt::new(10, 5);
@@ -1297,6 +4027,21 @@ mod tests {
         }
     }
 
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(2048))]
+
+        #[test]
+        fn prop_dump_cursor_outside_margins(input in gen_origin_input(30)) {
+            let mut vt1 = Vt::new(10, 5);
+            let mut vt2 = Vt::new(10, 5);
+
+            vt1.feed_str(&input);
+            vt2.feed_str(&vt1.dump());
+
+            assert_vts_eq(&vt1, &vt2);
+        }
+    }
+
     fn setup_dump_with_file() -> Result<(usize, usize, String, usize), env::VarError> {
         let path = env::var("P")?;
         let input = fs::read_to_string(path).unwrap();
@@ -1333,11 +4078,20 @@ mod tests {
         let mut lines = Vec::new();
         lines.extend(view[0..cursor_row].iter().map(|l| l.text()));
         let cursor_line = &view[cursor_row];
-        let left = cursor_line.chars().take(cursor_col);
-        let right = cursor_line.chars().skip(cursor_col);
-        let mut line = String::from_iter(left);
-        line.push('|');
-        line.extend(right);
+        let mut line = String::new();
+
+        for (col, cell) in cursor_line.cells().iter().enumerate() {
+            if col == cursor_col {
+                line.push('|');
+            }
+
+            cell.push_text(&mut line);
+        }
+
+        if cursor_col >= cursor_line.len() {
+            line.push('|');
+        }
+
         lines.push(line);
         lines.extend(view[cursor_row + 1..].iter().map(|l| l.text()));
 
@@ -1349,6 +4103,6 @@ mod tests {
     }
 
     fn wrapped(vt: &Vt) -> Vec<bool> {
-        vt.terminal.view().iter().map(|l| l.wrapped).collect()
+        vt.terminal.view().iter().map(|l| l.wrapped()).collect()
     }
 }